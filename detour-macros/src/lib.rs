@@ -0,0 +1,214 @@
+//! Procedural macros for [detour](https://docs.rs/detour).
+//!
+//! This crate should not be used directly; its macros are re-exported by
+//! `detour`.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, FnArg, Ident, ItemFn, LitStr, Pat, Path, ReturnType, Token};
+
+// It's documented where it's re-exported, by `detour`
+#[proc_macro_attribute]
+pub fn hook(attribute: TokenStream, item: TokenStream) -> TokenStream {
+  let arguments = parse_macro_input!(attribute as HookArguments);
+  let function = parse_macro_input!(item as ItemFn);
+
+  expand(arguments, function)
+    .unwrap_or_else(|error| error.to_compile_error())
+    .into()
+}
+
+/// The target of a hook.
+enum Target {
+  /// A function referred to by its path.
+  Path(Path),
+  /// A symbol within a module.
+  Symbol { module: LitStr, symbol: LitStr },
+}
+
+/// The arguments of the `hook` attribute.
+struct HookArguments {
+  target: Target,
+}
+
+impl Parse for HookArguments {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let (mut path, mut module, mut symbol) = (None, None, None);
+
+    let arguments = Punctuated::<HookArgument, Token![,]>::parse_terminated(input)?;
+    for argument in arguments {
+      match argument {
+        HookArgument::Target(value) => path = Some(value),
+        HookArgument::Module(value) => module = Some(value),
+        HookArgument::Symbol(value) => symbol = Some(value),
+      }
+    }
+
+    let target = match (path, symbol) {
+      (Some(path), None) if module.is_none() => Target::Path(path),
+      (None, Some(symbol)) => Target::Symbol {
+        module: module.unwrap_or_else(|| LitStr::new("", Span::call_site())),
+        symbol,
+      },
+      _ => {
+        return Err(syn::Error::new(
+          Span::call_site(),
+          "expected either `target = path` or `symbol = \"name\"` (with an optional `module`)",
+        ))
+      },
+    };
+
+    Ok(HookArguments { target })
+  }
+}
+
+/// A single argument of the `hook` attribute.
+enum HookArgument {
+  Target(Path),
+  Module(LitStr),
+  Symbol(LitStr),
+}
+
+impl Parse for HookArgument {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let key: Ident = input.parse()?;
+    input.parse::<Token![=]>()?;
+
+    match key.to_string().as_str() {
+      "target" => input.parse().map(HookArgument::Target),
+      "module" => input.parse().map(HookArgument::Module),
+      "symbol" => input.parse().map(HookArgument::Symbol),
+      _ => Err(syn::Error::new(key.span(), "unknown hook argument")),
+    }
+  }
+}
+
+/// Expands a hook into a static detour, its detour function and its
+/// registration.
+fn expand(arguments: HookArguments, function: ItemFn) -> syn::Result<TokenStream2> {
+  let ItemFn {
+    attrs,
+    vis,
+    sig,
+    block,
+  } = function;
+
+  if let Some(constness) = &sig.constness {
+    Err(syn::Error::new(
+      constness.span(),
+      "a hook cannot be `const`",
+    ))?;
+  }
+  if let Some(asyncness) = &sig.asyncness {
+    Err(syn::Error::new(
+      asyncness.span(),
+      "a hook cannot be `async`",
+    ))?;
+  }
+  if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+    Err(syn::Error::new(
+      sig.generics.span(),
+      "a hook cannot be generic",
+    ))?;
+  }
+  if let Some(variadic) = &sig.variadic {
+    Err(syn::Error::new(
+      variadic.span(),
+      "a hook cannot be variadic",
+    ))?;
+  }
+
+  let mut types = Vec::new();
+  for input in &sig.inputs {
+    match input {
+      FnArg::Typed(argument) => match &*argument.pat {
+        Pat::Ident(pat) if pat.ident == "original" => Err(syn::Error::new(
+          pat.ident.span(),
+          "`original` is reserved for calling the original function",
+        ))?,
+        _ => types.push(argument.ty.clone()),
+      },
+      FnArg::Receiver(receiver) => Err(syn::Error::new(
+        receiver.span(),
+        "a hook cannot have a receiver",
+      ))?,
+    }
+  }
+
+  let names = (0..types.len())
+    .map(|index| format_ident!("__arg_{}", index))
+    .collect::<Vec<_>>();
+  let output = match &sig.output {
+    ReturnType::Default => quote!(()),
+    ReturnType::Type(_, ty) => quote!(#ty),
+  };
+
+  let name = &sig.ident;
+  let (unsafety, abi, inputs) = (&sig.unsafety, &sig.abi, &sig.inputs);
+  let fn_type = quote!(#unsafety #abi fn(#(#types),*) -> #output);
+
+  // The target may be safe or not, as long as the function can detour it
+  let target_type = quote!(unsafe #abi fn(#(#types),*) -> #output);
+
+  // Documentation applies to the static detour, everything else to the detour
+  let (docs, attrs): (Vec<_>, Vec<_>) = attrs
+    .into_iter()
+    .partition(|attribute| attribute.path.is_ident("doc"));
+
+  let target = match arguments.target {
+    Target::Path(path) => quote!(#path),
+    Target::Symbol { module, symbol } => {
+      quote!(::detour::__private::symbol::<#target_type>(#module, #symbol)?)
+    },
+  };
+
+  let stmts = &block.stmts;
+  Ok(quote! {
+    #(#docs)*
+    #[allow(non_upper_case_globals)]
+    #vis static #name: ::detour::StaticDetour<#target_type> = {
+      #[inline(never)]
+      #[allow(unused_unsafe)]
+      unsafe #abi fn __ffi_detour(#(#names: #types),*) -> #output {
+        #[allow(unused_unsafe)]
+        #name.__detour(#(#names),*)
+      }
+
+      ::detour::StaticDetour::__new(__ffi_detour, concat!(module_path!(), "::", stringify!(#name)))
+    };
+
+    const _: () = {
+      #(#attrs)*
+      #unsafety fn __hook(#inputs) -> #output {
+        /// Calls the original function.
+        #[allow(dead_code)]
+        #unsafety fn original(#(#names: #types),*) -> #output {
+          #[allow(unused_unsafe)]
+          unsafe { #name.call(#(#names),*) }
+        }
+
+        #(#stmts)*
+      }
+
+      #[allow(unused_unsafe)]
+      unsafe fn __install() -> ::detour::Result<()> {
+        ::detour::__private::assert_hookable::<#target_type, #fn_type>();
+        let target: #target_type = #target;
+
+        match #name.initialize(target, |#(#names),*| unsafe { __hook(#(#names),*) }) {
+          Ok(_) | Err(::detour::Error::AlreadyInitialized) => #name.enable(),
+          Err(error) => Err(error),
+        }
+      }
+
+      ::detour::__private::inventory::submit! {
+        ::detour::__private::Hook::new(__install)
+      }
+    };
+  })
+}
@@ -1,18 +1,16 @@
 use std::ops::Range;
 use std::slice;
 
-use slice_pool::sync::{SliceBox, SlicePool};
-
 use super::search as region_search;
 use crate::error::{Error, Result};
 
 /// Defines the allocation type.
-pub type Allocation = SliceBox<u8>;
+pub type Allocation = &'static mut [u8];
 
 /// Shared instance containing all pools
 pub struct ProximityAllocator {
   pub max_distance: usize,
-  pub pools: Vec<SlicePool<u8>>,
+  pub pools: Vec<MemoryPool>,
 }
 
 impl ProximityAllocator {
@@ -24,16 +22,18 @@ impl ProximityAllocator {
     // Check if an existing pool can handle the allocation request
     self.allocate_memory(&memory_range, size).or_else(|_| {
       // ... otherwise allocate a pool within the memory range
-      self.allocate_pool(&memory_range, origin, size).map(|pool| {
-        // Use the newly allocated pool for the request
-        let allocation = pool.alloc(size).unwrap();
-        self.pools.push(pool);
-        allocation
-      })
+      self
+        .allocate_pool(&memory_range, origin, size)
+        .map(|mut pool| {
+          // Use the newly allocated pool for the request
+          let allocation = pool.alloc(size).unwrap();
+          self.pools.push(pool);
+          allocation
+        })
     })
   }
 
-  /// Releases the memory pool associated with an allocation.
+  /// Releases an allocation, and its memory pool if it was the last one.
   pub fn release(&mut self, value: &Allocation) {
     // Find the associated memory pool
     let index = self
@@ -48,8 +48,8 @@ impl ProximityAllocator {
       })
       .expect("retrieving associated memory pool");
 
-    // Release the pool if the associated allocation is unique
-    if self.pools[index].len() == 1 {
+    self.pools[index].release(value);
+    if self.pools[index].chunks.is_empty() {
       self.pools.remove(index);
     }
   }
@@ -57,7 +57,7 @@ impl ProximityAllocator {
   /// Allocates a chunk using any of the existing pools.
   fn allocate_memory(&mut self, range: &Range<usize>, size: usize) -> Result<Allocation> {
     // Returns true if the pool's memory is within the range
-    let is_pool_in_range = |pool: &SlicePool<u8>| {
+    let is_pool_in_range = |pool: &MemoryPool| {
       let lower = pool.as_ptr() as usize;
       let upper = lower + pool.len();
       range.contains(&lower) && range.contains(&(upper - 1))
@@ -84,7 +84,7 @@ impl ProximityAllocator {
     range: &Range<usize>,
     origin: *const (),
     size: usize,
-  ) -> Result<SlicePool<u8>> {
+  ) -> Result<MemoryPool> {
     let before = region_search::before(origin, Some(range.clone()));
     let after = region_search::after(origin, Some(range.clone()));
 
@@ -102,7 +102,7 @@ impl ProximityAllocator {
   }
 
   /// Tries to allocate fixed memory at the specified address.
-  fn allocate_fixed_pool(address: *const (), size: usize) -> Option<SlicePool<u8>> {
+  fn allocate_fixed_pool(address: *const (), size: usize) -> Option<MemoryPool> {
     // Try to allocate memory at the specified address
     mmap::MemoryMap::new(
       size,
@@ -114,36 +114,96 @@ impl ProximityAllocator {
       ],
     )
     .ok()
-    .map(SliceableMemoryMap)
-    .map(SlicePool::new)
+    .map(|map| MemoryPool {
+      map,
+      chunks: Vec::new(),
+    })
   }
 }
 
-// TODO: Use memmap-rs instead
-/// A wrapper for making a memory map compatible with `SlicePool`.
-struct SliceableMemoryMap(mmap::MemoryMap);
+/// A memory map, divided into allocated chunks.
+pub struct MemoryPool {
+  map: mmap::MemoryMap,
+  // The allocated ranges, ordered by their offset
+  chunks: Vec<Range<usize>>,
+}
+
+impl MemoryPool {
+  /// Allocates a chunk within the first gap large enough.
+  fn alloc(&mut self, size: usize) -> Option<Allocation> {
+    let mut index = 0;
+    let mut offset = 0;
 
-impl SliceableMemoryMap {
-  pub fn as_slice(&self) -> &[u8] {
-    unsafe { slice::from_raw_parts(self.0.data(), self.0.len()) }
+    // Skip any gaps between the chunks which are too small
+    while index < self.chunks.len() && self.chunks[index].start - offset < size {
+      offset = self.chunks[index].end;
+      index += 1;
+    }
+
+    if index == self.chunks.len() && self.len() - offset < size {
+      return None;
+    }
+
+    self.chunks.insert(index, offset..offset + size);
+    Some(unsafe { slice::from_raw_parts_mut(self.map.data().add(offset), size) })
   }
 
-  pub fn as_mut_slice(&mut self) -> &mut [u8] {
-    unsafe { slice::from_raw_parts_mut(self.0.data(), self.0.len()) }
+  /// Releases a chunk allocated from the pool.
+  fn release(&mut self, value: &Allocation) {
+    let offset = value.as_ptr() as usize - self.as_ptr() as usize;
+    let index = self
+      .chunks
+      .binary_search_by_key(&offset, |chunk| chunk.start)
+      .expect("releasing chunk");
+    self.chunks.remove(index);
   }
-}
 
-impl AsRef<[u8]> for SliceableMemoryMap {
-  fn as_ref(&self) -> &[u8] {
-    self.as_slice()
+  /// Returns the address of the memory map.
+  fn as_ptr(&self) -> *const u8 {
+    self.map.data()
   }
-}
 
-impl AsMut<[u8]> for SliceableMemoryMap {
-  fn as_mut(&mut self) -> &mut [u8] {
-    self.as_mut_slice()
+  /// Returns the size of the memory map.
+  fn len(&self) -> usize {
+    self.map.len()
   }
 }
 
-unsafe impl Send for SliceableMemoryMap {}
-unsafe impl Sync for SliceableMemoryMap {}
+unsafe impl Send for MemoryPool {}
+unsafe impl Sync for MemoryPool {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn allocations_never_overlap() -> Result<()> {
+    let mut allocator = ProximityAllocator {
+      max_distance: 0x4000_0000,
+      pools: Vec::new(),
+    };
+    let origin = allocations_never_overlap as *const ();
+
+    // Releasing adjacent chunks leaves a gap next to the unused memory
+    let mut chunks = (0..4)
+      .map(|_| allocator.allocate(origin, 16))
+      .collect::<Result<Vec<_>>>()?;
+    for chunk in chunks.drain(1..) {
+      allocator.release(&chunk);
+    }
+    chunks.push(allocator.allocate(origin, 16)?);
+    chunks.push(allocator.allocate(origin, 16)?);
+
+    let mut addresses = chunks
+      .iter()
+      .map(|chunk| chunk.as_ptr() as usize)
+      .collect::<Vec<_>>();
+    addresses.sort_unstable();
+    assert!(addresses.windows(2).all(|pair| pair[1] - pair[0] >= 16));
+
+    for chunk in &chunks {
+      allocator.release(chunk);
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,94 @@
+use crate::arch::{meta, Patcher, Trampoline};
+use crate::error::{Error, Result};
+use crate::util;
+use std::ops::Range;
+
+/// How a target's code would be patched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchStrategy {
+  /// A jump replaces the first instructions of the target.
+  Jump,
+  /// A jump replaces the target's instructions, and the padding after them.
+  JumpIntoPadding,
+  /// A short jump at the target leads to a jump in the padding preceding it.
+  HotPatch,
+}
+
+/// The outcome of a hookability analysis.
+#[derive(Debug, Clone)]
+pub struct HookPlan {
+  /// The address of the analyzed function.
+  pub target: *const (),
+  /// How the target would be patched.
+  pub strategy: PatchStrategy,
+  /// The address range that would be overwritten.
+  pub patch_area: Range<usize>,
+  /// The bytes that would be overwritten.
+  pub original_bytes: Vec<u8>,
+  /// The amount of the target's bytes that would be relocated.
+  pub prolog_size: usize,
+  /// The size of the trampoline that would be generated.
+  pub trampoline_size: usize,
+}
+
+impl HookPlan {
+  /// Returns whether a relay would be required to reach a detour.
+  pub fn requires_relay(&self, detour: *const ()) -> bool {
+    meta::relay_builder(self.target, detour).map_or(false, |relay| relay.is_some())
+  }
+}
+
+unsafe impl Send for HookPlan {}
+unsafe impl Sync for HookPlan {}
+
+/// Analyzes whether a function can be hooked, and how.
+///
+/// The same analysis as when a detour is created is performed, but without
+/// allocating any memory, nor modifying any protection. If the function cannot
+/// be hooked, the error a detour would fail with is returned.
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::PatchStrategy;
+///
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let plan = unsafe { detour::analyze(add5 as *const ())? };
+///
+/// assert_eq!(plan.strategy, PatchStrategy::Jump);
+/// assert_eq!(plan.patch_area.start, add5 as *const () as usize);
+/// # Ok(())
+/// # }
+/// ```
+pub unsafe fn analyze(target: *const ()) -> Result<HookPlan> {
+  if !util::is_executable_address(target)? {
+    Err(Error::NotExecutable)?;
+  }
+
+  let margin = meta::prolog_margin(target);
+  let trampoline = Trampoline::new(target, margin)?;
+  let patch_area = Patcher::patch_area(target, trampoline.prolog_size())?;
+
+  let start = patch_area.as_ptr() as usize;
+  let strategy = if start < target as usize {
+    PatchStrategy::HotPatch
+  } else if trampoline.prolog_size() < patch_area.len() {
+    PatchStrategy::JumpIntoPadding
+  } else {
+    PatchStrategy::Jump
+  };
+
+  Ok(HookPlan {
+    target,
+    strategy,
+    patch_area: start..start + patch_area.len(),
+    original_bytes: patch_area.to_vec(),
+    prolog_size: trampoline.prolog_size(),
+    trampoline_size: trampoline.emitter().len(),
+  })
+}
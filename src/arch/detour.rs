@@ -1,20 +1,48 @@
 use super::memory;
 use crate::error::{Error, Result};
-use crate::{alloc, arch, util};
+use crate::info::{DetourInfo, RelocatedInstruction};
+use crate::tls::Keys;
+use crate::{alloc, arch, registry, thread, util};
 use std::cell::UnsafeCell;
-use std::fmt;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::MutexGuard;
+use std::{fmt, mem};
+
+thread_local! {
+  /// The per-thread detours the current thread has opted in to.
+  static ACTIVATED: Keys = const { Keys::new() };
+}
+
+/// The key of the next detour created.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(1);
 
 /// An architecture-independent implementation of a base detour.
 ///
 /// This class is never instantiated by itself, it merely exposes an API
 /// available through it's descendants.
 pub struct Detour {
-  #[allow(dead_code)]
   relay: Option<alloc::ExecutableMemory>,
   trampoline: alloc::ExecutableMemory,
+  instruction_offsets: Vec<(usize, usize)>,
+  continuations: Vec<(Range<usize>, usize)>,
+  prolog_size: usize,
   patcher: UnsafeCell<arch::Patcher>,
+  dispatch: UnsafeCell<Option<(alloc::ExecutableMemory, Box<Dispatch>)>>,
   enabled: AtomicBool,
+  explicit: AtomicBool,
+  scopes: AtomicUsize,
+  per_thread: AtomicBool,
+  key: usize,
+  target: *const (),
+  detour: *const (),
+}
+
+/// The destinations of a per-thread detour's dispatch stub.
+struct Dispatch {
+  key: usize,
+  detour: *const (),
+  trampoline: *const (),
 }
 
 impl Detour {
@@ -42,21 +70,40 @@ impl Detour {
     };
 
     // If a relay is supplied, use it instead of the detour address
-    let detour = relay
+    let destination = relay
       .as_ref()
       .map(|code| code.as_ptr() as *const ())
       .unwrap_or(detour);
 
-    Ok(Detour {
+    let result = Detour {
       patcher: UnsafeCell::new(arch::Patcher::new(
         target,
-        detour,
+        destination,
         trampoline.prolog_size(),
       )?),
       trampoline: memory::allocate_pic(&mut pool, trampoline.emitter(), target)?,
+      instruction_offsets: trampoline.instruction_offsets().to_vec(),
+      continuations: trampoline.continuations().to_vec(),
+      prolog_size: trampoline.prolog_size(),
+      dispatch: UnsafeCell::new(None),
       enabled: AtomicBool::default(),
+      explicit: AtomicBool::default(),
+      scopes: AtomicUsize::default(),
+      per_thread: AtomicBool::default(),
+      key: NEXT_KEY.fetch_add(1, Ordering::SeqCst),
+      detour: destination,
       relay,
-    })
+      target,
+    };
+
+    mem::drop(pool);
+    registry::insert(
+      result.key(),
+      target,
+      detour,
+      result.trampoline.as_ptr() as *const (),
+    );
+    Ok(result)
   }
 
   /// Enables the detour.
@@ -74,6 +121,108 @@ impl Detour {
     self.enabled.load(Ordering::SeqCst)
   }
 
+  /// Sets whether the detour only applies to threads which have opted in.
+  ///
+  /// The target is redirected to a stub, which dispatches each call to either
+  /// the detour or the trampoline, depending on the calling thread.
+  pub unsafe fn set_per_thread(&self, per_thread: bool) -> Result<()> {
+    let mut pool = memory::POOL.lock().unwrap();
+
+    if self.is_per_thread() == per_thread {
+      return Ok(());
+    }
+
+    let dispatch = &mut *self.dispatch.get();
+    if dispatch.is_none() {
+      let data = Box::new(Dispatch {
+        key: self.key(),
+        detour: self.detour,
+        trampoline: self.trampoline.as_ptr() as *const (),
+      });
+
+      let emitter = arch::selector_builder(
+        select as *const () as usize,
+        &*data as *const Dispatch as usize,
+      );
+      *dispatch = Some((
+        memory::allocate_pic(&mut pool, &emitter, self.target)?,
+        data,
+      ));
+    }
+
+    let destination = if per_thread {
+      dispatch.as_ref().unwrap().0.as_ptr() as *const ()
+    } else {
+      self.detour
+    };
+
+    if self.is_enabled() {
+      // The patch is replaced whilst all other threads are suspended
+      let _handles = Self::protect_areas(&[(self, true)])?;
+      let breakpoint = thread::Breakpoint::new();
+      let freeze = thread::Freeze::new()?;
+
+      self.apply(false, &freeze, breakpoint.as_ref());
+      (*self.patcher.get()).set_destination(destination);
+      self.per_thread.store(per_thread, Ordering::SeqCst);
+      self.apply(true, &freeze, breakpoint.as_ref());
+    } else {
+      (*self.patcher.get()).set_destination(destination);
+      self.per_thread.store(per_thread, Ordering::SeqCst);
+    }
+
+    Ok(())
+  }
+
+  /// Returns whether the detour only applies to threads which have opted in.
+  pub fn is_per_thread(&self) -> bool {
+    self.per_thread.load(Ordering::SeqCst)
+  }
+
+  /// Opts in the current thread, when the detour is applied per thread.
+  pub fn enable_for_current_thread(&self) -> Result<()> {
+    if self.is_enabled_for_current_thread() {
+      return Ok(());
+    }
+
+    // The key is stored at most once, and nothing is stored if it fails
+    if ACTIVATED.try_with(|activated| activated.insert(self.key())) != Ok(true) {
+      Err(Error::OutOfMemory)?;
+    }
+    Ok(())
+  }
+
+  /// Opts out the current thread, when the detour is applied per thread.
+  pub fn disable_for_current_thread(&self) {
+    // Only a key which could be stored is removed
+    if self.is_enabled_for_current_thread() {
+      let _ = ACTIVATED.try_with(|activated| activated.remove(self.key()));
+    }
+  }
+
+  /// Returns whether the current thread has opted in.
+  pub fn is_enabled_for_current_thread(&self) -> bool {
+    is_activated(self.key())
+  }
+
+  /// Returns whether the detour is enabled within any scope.
+  pub fn is_scoped(&self) -> bool {
+    self.scopes.load(Ordering::SeqCst) > 0
+  }
+
+  /// Returns whether an address is within the detour's relay or trampoline.
+  pub fn contains(&self, address: usize) -> bool {
+    self.relay.iter().chain(Some(&self.trampoline)).any(|code| {
+      let start = code.as_ptr() as usize;
+      (start..start + code.len()).contains(&address)
+    })
+  }
+
+  /// Returns the address of the patched code.
+  pub fn target(&self) -> *const () {
+    self.target
+  }
+
   /// Returns a reference to the generated trampoline.
   pub fn trampoline(&self) -> &() {
     unsafe {
@@ -83,35 +232,293 @@ impl Detour {
     }
   }
 
+  /// Assigns a label to the detour, within the registry.
+  pub fn set_label(&self, label: Option<String>) {
+    registry::set_label(self.key(), label);
+  }
+
+  /// Returns a description of how the detour is applied.
+  pub fn info(&self) -> DetourInfo {
+    let patcher = unsafe { &*self.patcher.get() };
+    let area = patcher.area().as_ptr() as usize;
+    let trampoline = self.trampoline.as_ptr() as usize;
+    let target = self.target as usize;
+
+    DetourInfo {
+      target: self.target,
+      patch_area: area..area + patcher.area().len(),
+      original_bytes: patcher.original_prolog().to_vec(),
+      patched_bytes: patcher.detour_prolog().to_vec(),
+      prolog_size: self.prolog_size,
+      trampoline: trampoline as *const (),
+      trampoline_size: self.trampoline.len(),
+      relay: self.relay.as_ref().map(|relay| relay.as_ptr() as *const ()),
+      uses_hot_patch: area < target,
+      relocated_instructions: self
+        .instruction_offsets
+        .iter()
+        .map(|&(source, destination)| RelocatedInstruction {
+          original: (target + source) as *const (),
+          relocated: (trampoline + destination) as *const (),
+        })
+        .collect(),
+    }
+  }
+
   /// Enables or disables the detour.
   unsafe fn toggle(&self, enabled: bool) -> Result<()> {
-    let _guard = memory::POOL.lock().unwrap();
+    Self::toggle_all(&[(self, enabled)])
+  }
 
-    if self.enabled.load(Ordering::SeqCst) == enabled {
-      return Ok(());
+  /// Enables or disables several detours at once.
+  ///
+  /// Either every operation is applied, or none of them (see `patch_all`). A
+  /// detour remains enabled as long as it's enabled within any scope.
+  pub unsafe fn toggle_all(operations: &[(&Detour, bool)]) -> Result<()> {
+    Self::toggle_all_locked(memory::POOL.lock().unwrap(), operations)
+  }
+
+  /// Enables or disables several detours at once, with the memory lock
+  /// already held. It's released before any events are emitted.
+  pub unsafe fn toggle_all_locked(
+    guard: MutexGuard<'_, alloc::ThreadAllocator>,
+    operations: &[(&Detour, bool)],
+  ) -> Result<()> {
+    let patches = operations
+      .iter()
+      .map(|&(detour, enabled)| (detour, enabled || detour.scopes.load(Ordering::SeqCst) > 0))
+      .collect::<Vec<_>>();
+    let applied = Self::patch_all(&patches)?;
+
+    for &(detour, enabled) in operations {
+      detour.explicit.store(enabled, Ordering::SeqCst);
+    }
+
+    mem::drop(guard);
+    Self::register_all(&applied);
+    Ok(())
+  }
+
+  /// Enables the detour within a scope, until it's ended.
+  pub unsafe fn enter_scope(&self) -> Result<()> {
+    self.enter_scope_locked(memory::POOL.lock().unwrap())
+  }
+
+  /// Enables the detour within a scope, with the memory lock already held.
+  pub unsafe fn enter_scope_locked(
+    &self,
+    guard: MutexGuard<'_, alloc::ThreadAllocator>,
+  ) -> Result<()> {
+    let applied = Self::patch_all(&[(self, true)])?;
+    self.scopes.fetch_add(1, Ordering::SeqCst);
+
+    mem::drop(guard);
+    Self::register_all(&applied);
+    Ok(())
+  }
+
+  /// Ends a scope, disabling the detour if it was the last one, unless it has
+  /// been enabled explicitly.
+  pub unsafe fn exit_scope(&self) -> Result<()> {
+    let guard = memory::POOL.lock().unwrap();
+    let applied =
+      if self.scopes.fetch_sub(1, Ordering::SeqCst) == 1 && !self.explicit.load(Ordering::SeqCst) {
+        Self::patch_all(&[(self, false)])?
+      } else {
+        Vec::new()
+      };
+
+    mem::drop(guard);
+    Self::register_all(&applied);
+    Ok(())
+  }
+
+  /// Disables the detour for good, unless it's enabled within any scope.
+  ///
+  /// The closure is invoked before the memory lock is released, so the detour
+  /// can be made unreachable before anyone may enable it again.
+  pub unsafe fn retire<F: FnOnce()>(
+    &self,
+    guard: MutexGuard<'_, alloc::ThreadAllocator>,
+    release: F,
+  ) -> Result<()> {
+    // A scope's guard refers to the detour, so it must end first
+    if self.is_scoped() {
+      Err(Error::InUse)?;
+    }
+
+    let applied = Self::patch_all(&[(self, false)])?;
+    self.explicit.store(false, Ordering::SeqCst);
+    release();
+
+    mem::drop(guard);
+    Self::register_all(&applied);
+    Ok(())
+  }
+
+  /// Patches or unpatches several detours, whilst the memory lock is held,
+  /// returning the ones which were changed.
+  ///
+  /// All detours are toggled whilst other threads are suspended, and each
+  /// affected page only has its protection changed once. Everything that may
+  /// fail is done before any code is modified, so either every detour is
+  /// toggled, or none of them.
+  unsafe fn patch_all<'a>(operations: &[(&'a Detour, bool)]) -> Result<Vec<(&'a Detour, bool)>> {
+    if operations
+      .iter()
+      .all(|(detour, enabled)| detour.is_enabled() == *enabled)
+    {
+      return Ok(Vec::new());
     }
 
     // Runtime code is by default only read-execute
-    let _handle = {
-      let area = (*self.patcher.get()).area();
-      region::protect_with_handle(
-        area.as_ptr(),
-        area.len(),
-        region::Protection::READ_WRITE_EXECUTE,
-      )
-    }?;
-
-    // Copy either the detour or the original bytes of the function
-    (*self.patcher.get()).toggle(enabled);
+    let _handles = Self::protect_areas(operations)?;
+
+    // Nothing may be allocated whilst other threads are suspended
+    let mut applied: Vec<(&Detour, bool)> = Vec::with_capacity(operations.len());
+    let breakpoint = thread::Breakpoint::new();
+
+    // Other threads may be executing the instructions that are replaced
+    let freeze = thread::Freeze::new()?;
+
+    for &(detour, enabled) in operations {
+      if detour.is_enabled() != enabled {
+        detour.apply(enabled, &freeze, breakpoint.as_ref());
+        applied.push((detour, enabled));
+      }
+    }
+
+    Ok(applied)
+  }
+
+  /// Reports toggled detours to the registry, once the memory lock has been
+  /// released.
+  fn register_all(applied: &[(&Detour, bool)]) {
+    for &(detour, enabled) in applied {
+      registry::toggle(detour.key(), enabled);
+    }
+  }
+
+  /// Makes the patch areas of several detours writable. Areas sharing pages
+  /// are merged, so each page only has its protection changed once.
+  unsafe fn protect_areas(operations: &[(&Detour, bool)]) -> Result<Vec<region::ProtectGuard>> {
+    let mut ranges = operations
+      .iter()
+      .map(|(detour, _)| {
+        let area = (*detour.patcher.get()).area();
+        let address = area.as_ptr() as usize;
+        region::page::floor(address)..region::page::ceil(address + area.len())
+      })
+      .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut pages: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+      match pages.last_mut() {
+        Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+        _ => pages.push(range),
+      }
+    }
+
+    pages
+      .into_iter()
+      .map(|range| {
+        region::protect_with_handle(
+          range.start as *const u8,
+          range.end - range.start,
+          region::Protection::READ_WRITE_EXECUTE,
+        )
+        .map_err(Error::from)
+      })
+      .collect()
+  }
+
+  /// Copies either the detour or the original bytes of the function, whilst
+  /// all other threads (except any which cannot be suspended) are suspended.
+  unsafe fn apply(
+    &self,
+    enabled: bool,
+    freeze: &thread::Freeze,
+    breakpoint: Option<&thread::Breakpoint>,
+  ) {
+    let trampoline = self.trampoline.as_ptr() as *const ();
+    (*self.patcher.get()).toggle(enabled, trampoline, breakpoint);
+    freeze.relocate(|address| self.relocate(address, enabled));
     self.enabled.store(enabled, Ordering::SeqCst);
-    Ok(())
+  }
+
+  /// Returns where a suspended thread should resume after a toggle, in case
+  /// its instruction pointer is within the modified code.
+  unsafe fn relocate(&self, address: usize, enabled: bool) -> Option<usize> {
+    let target = self.target as usize;
+    let trampoline = self.trampoline.as_ptr() as usize;
+
+    if enabled {
+      // Threads within the prolog continue in the trampoline. A thread at the
+      // very first instruction is left as is, since it has yet to enter.
+      self
+        .instruction_offsets
+        .iter()
+        .skip(1)
+        .find(|(source, _)| target + source == address)
+        .map(|(_, destination)| trampoline + destination)
+    } else {
+      let area = (*self.patcher.get()).area();
+      let area_start = area.as_ptr() as usize;
+
+      if (area_start..target).contains(&address) {
+        // A thread within a hot patch area was on its way to the detour
+        Some(self.destination() as usize)
+      } else {
+        // Threads within the trampoline continue in the original prolog,
+        // since the trampoline may be released after it has been disabled.
+        self
+          .continuations
+          .iter()
+          .find(|(range, _)| range.contains(&address.wrapping_sub(trampoline)))
+          .map(|(_, source)| target + source)
+      }
+    }
+  }
+
+  /// Returns the address the target is redirected to.
+  unsafe fn destination(&self) -> *const () {
+    match &*self.dispatch.get() {
+      Some((stub, _)) if self.is_per_thread() => stub.as_ptr() as *const (),
+      _ => self.detour,
+    }
+  }
+
+  /// Returns the key identifying the detour within thread-local storage.
+  ///
+  /// Unlike any of its addresses, it's never reused by a later detour.
+  fn key(&self) -> usize {
+    self.key
+  }
+}
+
+/// Returns whether the current thread has opted in to a per-thread detour.
+fn is_activated(key: usize) -> bool {
+  ACTIVATED
+    .try_with(|activated| activated.contains(key))
+    .unwrap_or(false)
+}
+
+/// Selects where a call to a per-thread detour's target continues.
+unsafe extern "C" fn select(_stack: usize, dispatch: &Dispatch) -> *const () {
+  if is_activated(dispatch.key) {
+    dispatch.detour
+  } else {
+    dispatch.trampoline
   }
 }
 
 impl Drop for Detour {
   /// Disables the detour, if enabled.
   fn drop(&mut self) {
-    debug_assert!(unsafe { self.disable().is_ok() });
+    let result = unsafe { self.disable() };
+    debug_assert!(result.is_ok());
+    registry::remove(self.key());
   }
 }
 
@@ -129,3 +536,43 @@ impl fmt::Debug for Detour {
 
 unsafe impl Send for Detour {}
 unsafe impl Sync for Detour {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn relocate_within_trampoline() -> Result<()> {
+    extern "C" fn ret10() -> i32 {
+      10
+    }
+
+    // A relative call to the next instruction, followed by a return
+    let code = [0xE8, 0x00, 0x00, 0x00, 0x00, 0xC3];
+    let mut memory = memory::POOL
+      .lock()
+      .unwrap()
+      .allocate(ret10 as *const (), code.len())?;
+    memory[..code.len()].copy_from_slice(&code);
+
+    let target = memory.as_ptr() as usize;
+    let detour = unsafe { Detour::new(target as *const (), ret10 as *const ())? };
+    let trampoline = detour.trampoline.as_ptr() as usize;
+    let relocate = |offset| unsafe { detour.relocate(trampoline + offset, false) };
+
+    // Every part of the trampoline leads back to the original prolog
+    for offset in 0..detour.trampoline.len() {
+      assert!(relocate(offset).is_some());
+    }
+
+    // A thread yet to call continues at the call, and the trailing jump
+    // continues after the prolog.
+    assert_eq!(relocate(0), Some(target));
+    assert_eq!(relocate(detour.trampoline.len() - 1), Some(target + 5));
+
+    // A thread returning from the relocated call must not call again
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(relocate(6), Some(target + 5));
+    Ok(())
+  }
+}
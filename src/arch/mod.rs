@@ -11,23 +11,28 @@
 ///
 /// - A `Patcher`, modifies a target in-memory.
 /// - A `Trampoline`, generates a callable address to the target.
+pub use self::analysis::{analyze, HookPlan, PatchStrategy};
 pub use self::detour::Detour;
 
 use cfg_if::cfg_if;
 
-// TODO: flush instruction cache? __clear_cache
-// See: https://github.com/llvm-mirror/compiler-rt/blob/master/lib/builtins/clear_cache.c
+// The instruction cache is coherent on x86, but processors must be serialized
+// after code has been modified (see `thread::serialize`).
 cfg_if! {
     if #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
         mod x86;
         use self::x86::{Patcher, Trampoline, meta};
+        pub use self::x86::meta::{follow_jumps, relative_operand};
+        pub use self::x86::context::{selector_builder, stub_builder, Context};
+        pub use self::x86::variadic::{forwarder_builder, Arguments, Cursor};
     } else {
         // TODO: Implement ARM/AARCH64/MIPS support!
     }
 }
 
+mod analysis;
 mod detour;
-mod memory;
+pub mod memory;
 
 /// Returns true if the displacement is within a certain range.
 pub fn is_within_range(displacement: isize) -> bool {
@@ -0,0 +1,358 @@
+use crate::pic;
+use std::mem;
+
+cfg_if::cfg_if! {
+  if #[cfg(target_arch = "x86_64")] {
+    /// The processor state at a mid-function hook (x64).
+    ///
+    /// Modifications to any register, except `rsp`, are applied once the
+    /// callback returns.
+    #[repr(C, align(16))]
+    #[derive(Debug)]
+    pub struct Context {
+      pub xmm: [u128; 16],
+      pub r15: u64,
+      pub r14: u64,
+      pub r13: u64,
+      pub r12: u64,
+      pub r11: u64,
+      pub r10: u64,
+      pub r9: u64,
+      pub r8: u64,
+      pub rdi: u64,
+      pub rsi: u64,
+      pub rbp: u64,
+      pub rsp: u64,
+      pub rbx: u64,
+      pub rdx: u64,
+      pub rcx: u64,
+      pub rax: u64,
+      pub rflags: u64,
+      /// The address of the stack frame saved by the stub.
+      frame: u64,
+    }
+
+    /// The number of XMM registers saved in the context.
+    const XMM_REGISTERS: u8 = 16;
+
+    /// Saves the general-purpose registers & flags.
+    fn save_registers(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[
+        0x48, 0x8D, 0x64, 0x24, 0x80,             // lea rsp, [rsp-0x80] (red zone)
+        0x9C,                                     // pushfq
+        0x50,                                     // push rax
+        0x48, 0x89, 0xE0,                         // mov rax, rsp
+        0x48, 0x83, 0xE4, 0xF0,                   // and rsp, -16
+        0x50,                                     // push rax (frame)
+        0xFF, 0x70, 0x08,                         // push [rax+8] (rflags)
+        0xFF, 0x30,                               // push [rax] (rax)
+        0x51,                                     // push rcx
+        0x52,                                     // push rdx
+        0x53,                                     // push rbx
+        0x48, 0x8D, 0x88, 0x90, 0x00, 0x00, 0x00, // lea rcx, [rax+0x90]
+        0x51,                                     // push rcx (rsp)
+        0x55,                                     // push rbp
+        0x56,                                     // push rsi
+        0x57,                                     // push rdi
+        0x41, 0x50, 0x41, 0x51, 0x41, 0x52, 0x41, 0x53, // push r8-r11
+        0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57, // push r12-r15
+      ]);
+    }
+
+    /// Invokes `dispatch(context, data)`, with the context at the stack.
+    fn call_dispatch(code: &mut Vec<u8>, dispatch: usize, data: usize) {
+      if cfg!(windows) {
+        code.extend_from_slice(&[0x48, 0x89, 0xE1]); // mov rcx, rsp
+        code.extend_from_slice(&[0x48, 0xBA]); // mov rdx, data
+        code.extend_from_slice(&data.to_le_bytes());
+        code.extend_from_slice(&[0x48, 0x83, 0xEC, 0x20]); // sub rsp, 0x20 (shadow space)
+      } else {
+        code.extend_from_slice(&[0x48, 0x89, 0xE7]); // mov rdi, rsp
+        code.extend_from_slice(&[0x48, 0xBE]); // mov rsi, data
+        code.extend_from_slice(&data.to_le_bytes());
+      }
+
+      code.extend_from_slice(&[0x48, 0xB8]); // mov rax, dispatch
+      code.extend_from_slice(&dispatch.to_le_bytes());
+      code.extend_from_slice(&[0xFF, 0xD0]); // call rax
+
+      if cfg!(windows) {
+        code.extend_from_slice(&[0x48, 0x83, 0xC4, 0x20]); // add rsp, 0x20
+      }
+    }
+
+    /// Restores the general-purpose registers, flags & the stack pointer.
+    fn restore_registers(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[
+        0x41, 0x5F, 0x41, 0x5E, 0x41, 0x5D, 0x41, 0x5C, // pop r15-r12
+        0x41, 0x5B, 0x41, 0x5A, 0x41, 0x59, 0x41, 0x58, // pop r11-r8
+        0x5F,                                     // pop rdi
+        0x5E,                                     // pop rsi
+        0x5D,                                     // pop rbp
+        0x48, 0x83, 0xC4, 0x08,                   // add rsp, 8 (rsp)
+        0x5B,                                     // pop rbx
+        0x5A,                                     // pop rdx
+        0x59,                                     // pop rcx
+        0x58,                                     // pop rax
+        0x9D,                                     // popfq
+        0x5C,                                     // pop rsp (frame)
+        0x48, 0x8D, 0xA4, 0x24, 0x90, 0x00, 0x00, 0x00, // lea rsp, [rsp+0x90]
+      ]);
+    }
+
+    /// Saves the registers which may contain arguments.
+    fn save_arguments(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[
+        0x50,       // push rax (vector register count)
+        0x51,       // push rcx
+        0x52,       // push rdx
+        0x56,       // push rsi
+        0x57,       // push rdi
+        0x41, 0x50, // push r8
+        0x41, 0x51, // push r9
+        0x41, 0x52, // push r10
+      ]);
+
+      // The stack is aligned once the area (with its padding) is allocated
+      code.extend_from_slice(&sub_stack_pointer(ARGUMENT_AREA_SIZE));
+      (0..ARGUMENT_XMM_REGISTERS).for_each(|register| code.extend(movaps(true, register)));
+    }
+
+    /// Restores the registers which may contain arguments, and jumps to the
+    /// address returned by the dispatch.
+    fn restore_arguments(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[0x49, 0x89, 0xC3]); // mov r11, rax
+      (0..ARGUMENT_XMM_REGISTERS).for_each(|register| code.extend(movaps(false, register)));
+      code.extend_from_slice(&add_stack_pointer(ARGUMENT_AREA_SIZE));
+
+      code.extend_from_slice(&[
+        0x41, 0x5A, // pop r10
+        0x41, 0x59, // pop r9
+        0x41, 0x58, // pop r8
+        0x5F,       // pop rdi
+        0x5E,       // pop rsi
+        0x5A,       // pop rdx
+        0x59,       // pop rcx
+        0x58,       // pop rax
+        0x41, 0xFF, 0xE3, // jmp r11
+      ]);
+    }
+
+    /// The number of XMM registers which may contain arguments.
+    const ARGUMENT_XMM_REGISTERS: u8 = 8;
+
+    /// The size of the argument XMM register area, including its padding.
+    const ARGUMENT_AREA_SIZE: u32 = ARGUMENT_XMM_REGISTERS as u32 * 16 + 8;
+
+    /// Jumps to the address stored after the instruction.
+    fn jump_to_slot() -> Box<dyn pic::Thunkable> {
+      // jmp [rip+0]
+      let mut code = vec![0xFF, 0x25, 0x00, 0x00, 0x00, 0x00];
+      code.extend_from_slice(&[0; mem::size_of::<usize>()]);
+      Box::new(code)
+    }
+  } else {
+    /// The processor state at a mid-function hook (x86).
+    ///
+    /// Modifications to any register, except `esp`, are applied once the
+    /// callback returns.
+    #[repr(C, align(16))]
+    #[derive(Debug)]
+    pub struct Context {
+      pub xmm: [u128; 8],
+      pub edi: u32,
+      pub esi: u32,
+      pub ebp: u32,
+      pub esp: u32,
+      pub ebx: u32,
+      pub edx: u32,
+      pub ecx: u32,
+      pub eax: u32,
+      pub eflags: u32,
+      /// The address of the stack frame saved by the stub.
+      frame: u32,
+    }
+
+    /// The number of XMM registers saved in the context.
+    const XMM_REGISTERS: u8 = 8;
+
+    /// Saves the general-purpose registers & flags.
+    fn save_registers(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[
+        0x9C,             // pushfd
+        0x50,             // push eax
+        0x89, 0xE0,       // mov eax, esp
+        0x83, 0xE4, 0xF0, // and esp, -16
+        0x83, 0xEC, 0x08, // sub esp, 8 (alignment)
+        0x50,             // push eax (frame)
+        0xFF, 0x70, 0x04, // push [eax+4] (eflags)
+        0xFF, 0x30,       // push [eax] (eax)
+        0x51,             // push ecx
+        0x52,             // push edx
+        0x53,             // push ebx
+        0x8D, 0x48, 0x08, // lea ecx, [eax+8]
+        0x51,             // push ecx (esp)
+        0x55,             // push ebp
+        0x56,             // push esi
+        0x57,             // push edi
+      ]);
+    }
+
+    /// Invokes `dispatch(context, data)`, with the context at the stack.
+    fn call_dispatch(code: &mut Vec<u8>, dispatch: usize, data: usize) {
+      code.extend_from_slice(&[0x89, 0xE1]); // mov ecx, esp
+      code.extend_from_slice(&[0x83, 0xEC, 0x08]); // sub esp, 8 (alignment)
+      code.push(0x68); // push data
+      code.extend_from_slice(&(data as u32).to_le_bytes());
+      code.push(0x51); // push ecx
+      code.push(0xB8); // mov eax, dispatch
+      code.extend_from_slice(&(dispatch as u32).to_le_bytes());
+      code.extend_from_slice(&[0xFF, 0xD0]); // call eax
+      code.extend_from_slice(&[0x83, 0xC4, 0x10]); // add esp, 0x10
+    }
+
+    /// Restores the general-purpose registers, flags & the stack pointer.
+    fn restore_registers(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[
+        0x5F,                   // pop edi
+        0x5E,                   // pop esi
+        0x5D,                   // pop ebp
+        0x83, 0xC4, 0x04,       // add esp, 4 (esp)
+        0x5B,                   // pop ebx
+        0x5A,                   // pop edx
+        0x59,                   // pop ecx
+        0x58,                   // pop eax
+        0x9D,                   // popfd
+        0x5C,                   // pop esp (frame)
+        0x8D, 0x64, 0x24, 0x08, // lea esp, [esp+8]
+      ]);
+    }
+
+    /// Saves the registers which may contain arguments.
+    fn save_arguments(code: &mut Vec<u8>) {
+      // None of the supported calling conventions use XMM registers
+      code.extend_from_slice(&[
+        0x50,             // push eax
+        0x51,             // push ecx
+        0x52,             // push edx
+        0x89, 0xE0,       // mov eax, esp
+        0x83, 0xE4, 0xF0, // and esp, -16
+        0x83, 0xEC, 0x0C, // sub esp, 12 (alignment)
+        0x50,             // push eax (frame)
+      ]);
+    }
+
+    /// Restores the registers which may contain arguments, and jumps to the
+    /// address returned by the dispatch.
+    fn restore_arguments(code: &mut Vec<u8>) {
+      code.extend_from_slice(&[
+        0x5C,                   // pop esp (frame)
+        0x87, 0x44, 0x24, 0x08, // xchg eax, [esp+8]
+        0x5A,                   // pop edx
+        0x59,                   // pop ecx
+        0xC3,                   // ret
+      ]);
+    }
+
+    /// Jumps to the address stored after the instruction.
+    fn jump_to_slot() -> Box<dyn pic::Thunkable> {
+      Box::new(unsafe {
+        pic::UnsafeThunk::new(
+          |address| {
+            // jmp [slot]
+            let mut code = vec![0xFF, 0x25];
+            code.extend_from_slice(&(address as u32 + 6).to_le_bytes());
+            code.extend_from_slice(&[0; mem::size_of::<usize>()]);
+            code
+          },
+          6 + mem::size_of::<usize>(),
+        )
+      })
+    }
+  }
+}
+
+/// The size of the XMM register area.
+const XMM_AREA_SIZE: u32 = XMM_REGISTERS as u32 * 16;
+
+/// Creates a stub, which saves the processor state to a `Context`, invokes
+/// `dispatch(&mut context, data)`, restores the state and jumps to the address
+/// stored in the stub's last pointer-sized word.
+pub fn stub_builder(dispatch: usize, data: usize) -> pic::CodeEmitter {
+  let mut code = Vec::new();
+
+  save_registers(&mut code);
+  code.extend_from_slice(&sub_stack_pointer(XMM_AREA_SIZE));
+  (0..XMM_REGISTERS).for_each(|register| code.extend(movaps(true, register)));
+
+  call_dispatch(&mut code, dispatch, data);
+
+  (0..XMM_REGISTERS).for_each(|register| code.extend(movaps(false, register)));
+  code.extend_from_slice(&add_stack_pointer(XMM_AREA_SIZE));
+  restore_registers(&mut code);
+
+  let mut emitter = pic::CodeEmitter::new();
+  emitter.add_thunk(Box::new(code));
+  emitter.add_thunk(jump_to_slot());
+  emitter
+}
+
+/// Creates a stub, which saves the registers that may contain arguments,
+/// invokes `select(stack, data)`, restores the registers and jumps to the
+/// address returned.
+pub fn selector_builder(select: usize, data: usize) -> pic::CodeEmitter {
+  let mut code = Vec::new();
+
+  save_arguments(&mut code);
+  call_dispatch(&mut code, select, data);
+  restore_arguments(&mut code);
+
+  let mut emitter = pic::CodeEmitter::new();
+  emitter.add_thunk(Box::new(code));
+  emitter
+}
+
+/// Returns `movaps [sp+16*register], xmm` or `movaps xmm, [sp+16*register]`.
+fn movaps(store: bool, register: u8) -> Vec<u8> {
+  let mut code = Vec::new();
+  if register >= 8 {
+    code.push(0x44); // REX.R
+  }
+
+  let displacement = u32::from(register) * 16;
+  let modrm = ((register & 7) << 3) | 0b100;
+
+  code.extend_from_slice(&[0x0F, if store { 0x29 } else { 0x28 }]);
+  if displacement < 0x80 {
+    code.extend_from_slice(&[0x40 | modrm, 0x24, displacement as u8]);
+  } else {
+    code.extend_from_slice(&[0x80 | modrm, 0x24]);
+    code.extend_from_slice(&displacement.to_le_bytes());
+  }
+  code
+}
+
+/// Returns `sub sp, value`.
+fn sub_stack_pointer(value: u32) -> Vec<u8> {
+  let mut code = rex_w(vec![0x81, 0xEC]);
+  code.extend_from_slice(&value.to_le_bytes());
+  code
+}
+
+/// Returns `add sp, value`.
+fn add_stack_pointer(value: u32) -> Vec<u8> {
+  let mut code = rex_w(vec![0x81, 0xC4]);
+  code.extend_from_slice(&value.to_le_bytes());
+  code
+}
+
+/// Prefixes an instruction with REX.W, when operating on 64-bit registers.
+fn rex_w(code: Vec<u8>) -> Vec<u8> {
+  if cfg!(target_arch = "x86_64") {
+    let mut prefixed = vec![0x48];
+    prefixed.extend(code);
+    prefixed
+  } else {
+    code
+  }
+}
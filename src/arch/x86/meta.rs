@@ -1,10 +1,18 @@
 use super::thunk;
-use crate::{error::Result, pic};
-use std::mem;
+use super::trampoline::disasm::{describe, Disassembler, Instruction};
+use crate::error::{Error, Result};
+use crate::{pic, util};
+use std::{mem, ptr, slice};
 
 /// The furthest distance between a target and its detour (2 GiB).
 pub const DETOUR_RANGE: usize = 0x8000_0000;
 
+/// The maximum amount of jumps followed, to protect against cycles.
+const MAX_JUMPS: usize = 16;
+
+/// The `endbr64` & `endbr32` instructions, marking valid branch targets.
+const END_BRANCH: [[u8; 4]; 2] = [[0xF3, 0x0F, 0x1E, 0xFA], [0xF3, 0x0F, 0x1E, 0xFB]];
+
 /// Returns the preferred prolog size for the target.
 pub fn prolog_margin(_target: *const ()) -> usize {
   mem::size_of::<thunk::x86::JumpRel>()
@@ -22,3 +30,228 @@ pub fn relay_builder(target: *const (), detour: *const ()) -> Result<Option<pic:
     Ok(None)
   }
 }
+
+/// Follows any jump thunks at an address, and returns the code they lead to.
+///
+/// When a function pointer is obtained through a dynamically linked module,
+/// it often refers to a PLT stub or an incremental linking thunk, instead of
+/// the function itself. Detouring such a stub only affects callers using the
+/// same stub. This function disassembles the target and follows `jmp rel`,
+/// `jmp [rip+x]` (e.g GOT entries), `jmp [addr]` and `endbr; jmp` chains, and
+/// returns the address of the final code, which can be used as a target
+/// instead. The address patched by a detour is available from its `target`
+/// method.
+///
+/// A lazily bound PLT stub (i.e one whose GOT entry leads back to the
+/// dynamic linker) is not followed, since its destination is yet unknown.
+/// Neither is a jump to non-executable memory, in which case the address of
+/// the jump itself is returned.
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::{follow_jumps, RawDetour};
+///
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// fn add10(val: i32) -> i32 {
+///   val + 10
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let target = unsafe { follow_jumps(add5 as *const ())? };
+/// let hook = unsafe { RawDetour::new(target, add10 as *const ())? };
+/// assert_eq!(hook.target(), target);
+///
+/// unsafe { hook.enable()? };
+/// assert_eq!(add5(5), 15);
+/// # Ok(())
+/// # }
+/// ```
+pub unsafe fn follow_jumps(target: *const ()) -> Result<*const ()> {
+  if !util::is_executable_address(target)? {
+    Err(Error::NotExecutable)?;
+  }
+
+  // The chain is only followed as long as its destinations are executable
+  let mut target = target;
+  for _ in 0..MAX_JUMPS {
+    match jump_destination(target) {
+      Some(destination) => target = destination,
+      None => break,
+    }
+  }
+
+  Ok(target)
+}
+
+/// Returns the address referred to by an instruction's relative operand (i.e
+/// a branch's destination, or a RIP-relative memory operand).
+pub unsafe fn relative_operand(address: *const ()) -> Result<*const ()> {
+  let mut disassembler = Disassembler::new(address);
+  let instruction = Instruction::new(&mut disassembler, address)
+    .ok_or_else(|| Error::InvalidCode(describe(address, address as usize)))?;
+
+  let displacement = instruction
+    .relative_branch_displacement()
+    .or_else(|| instruction.rip_operand_displacement())
+    .ok_or_else(|| Error::UnsupportedInstruction(describe(address, address as usize)))?;
+
+  Ok(
+    instruction
+      .next_instruction_address()
+      .wrapping_add(displacement as usize) as *const (),
+  )
+}
+
+/// Returns the destination of a jump thunk, or `None` if the address does
+/// not contain one (or it cannot be resolved).
+unsafe fn jump_destination(address: *const ()) -> Option<*const ()> {
+  let instruction = next_instruction(address).filter(Instruction::is_unconditional_jump)?;
+
+  let next_address = instruction.next_instruction_address();
+  let destination = if let Some(displacement) = instruction.relative_branch_displacement() {
+    next_address.wrapping_add(displacement as usize)
+  } else if let Some(displacement) = instruction.rip_operand_displacement() {
+    read_pointer(next_address.wrapping_add(displacement as usize))?
+  } else {
+    // Register based jumps (e.g `jmp [ebx+0xC]`) cannot be resolved
+    read_pointer(instruction.absolute_memory_operand()?)?
+  };
+
+  let destination = destination as *const ();
+  if !util::is_executable_address(destination).unwrap_or(false) || is_lazy_binding(destination) {
+    None
+  } else {
+    Some(destination)
+  }
+}
+
+/// Returns whether an address contains the lazy binding part of a PLT entry,
+/// which an unbound entry's GOT slot leads to.
+///
+/// It pushes the relocation index and jumps to the PLT's first entry, which
+/// pushes a GOT entry and invokes the dynamic linker through another one, i.e
+/// `push imm; jmp PLT0` and `PLT0: push [GOT+x]; jmp [GOT+y]`.
+unsafe fn is_lazy_binding(address: *const ()) -> bool {
+  let is_lazy_binding = || {
+    let push = next_instruction(address).filter(Instruction::is_push_immediate)?;
+    let jump = next_instruction(push.next_instruction_address() as *const ())
+      .filter(Instruction::is_unconditional_jump)?;
+
+    let plt0 = jump
+      .next_instruction_address()
+      .wrapping_add(jump.relative_branch_displacement()? as usize);
+
+    let push = next_instruction(plt0 as *const ()).filter(Instruction::is_push_memory)?;
+    next_instruction(push.next_instruction_address() as *const ())
+      .filter(|jump| jump.is_unconditional_jump() && jump.relative_branch_displacement().is_none())
+  };
+
+  is_lazy_binding().is_some()
+}
+
+/// Disassembles the instruction at an address, skipping any `endbr`.
+///
+/// Returns `None` if the address is not executable, or not an instruction.
+unsafe fn next_instruction(address: *const ()) -> Option<Instruction> {
+  if !util::is_executable_address(address).unwrap_or(false) {
+    return None;
+  }
+
+  let prefix = slice::from_raw_parts(address as *const u8, END_BRANCH[0].len());
+  let address = if END_BRANCH.iter().any(|end_branch| end_branch == prefix) {
+    (address as usize + prefix.len()) as *const ()
+  } else {
+    address
+  };
+
+  let mut disassembler = Disassembler::new(address);
+  Instruction::new(&mut disassembler, address)
+}
+
+/// Reads a pointer from memory, if the memory is readable.
+unsafe fn read_pointer(address: usize) -> Option<usize> {
+  region::query(address as *const u8)
+    .ok()
+    .filter(|region| region.protection.contains(region::Protection::READ))
+    .map(|_| ptr::read_unaligned(address as *const usize))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alloc::ExecutableMemory;
+  use crate::arch::memory;
+
+  extern "C" fn ret10() -> i32 {
+    10
+  }
+
+  /// Copies machine code to executable memory.
+  fn executable_code(code: &[u8]) -> Result<ExecutableMemory> {
+    let mut memory = memory::POOL
+      .lock()
+      .unwrap()
+      .allocate(ret10 as *const (), code.len())?;
+    memory.copy_from_slice(code);
+    Ok(memory)
+  }
+
+  #[test]
+  fn follow_jumps_plt() -> Result<()> {
+    let mut memory = executable_code(&[0xCC; 64])?;
+    let base = memory.as_ptr() as usize;
+    let mut write =
+      |offset: usize, bytes: &[u8]| memory[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+    // An operand of `push [..]` and `jmp [..]`, either RIP-relative or absolute
+    let slot = |offset: usize, next: usize| -> [u8; 4] {
+      let operand = if cfg!(target_arch = "x86_64") {
+        offset.wrapping_sub(next)
+      } else {
+        base + offset
+      };
+      (operand as u32).to_le_bytes()
+    };
+
+    // PLT0: push [GOT+8]; jmp [GOT+16]
+    write(0, &[0xFF, 0x35]);
+    write(2, &slot(48, 6));
+    write(6, &[0xFF, 0x25]);
+    write(8, &slot(56, 12));
+
+    // PLT1: jmp [GOT+24]; push 0; jmp PLT0
+    write(16, &[0xFF, 0x25]);
+    write(18, &slot(40, 22));
+    write(22, &[0x68, 0x00, 0x00, 0x00, 0x00, 0xE9]);
+    write(28, &(-32i32).to_le_bytes());
+
+    // The GOT entry leads back to the PLT, so its destination is unknown
+    let plt1 = (base + 16) as *const ();
+    write(40, &(base + 22).to_ne_bytes());
+    assert_eq!(unsafe { follow_jumps(plt1)? }, plt1);
+
+    write(40, &(ret10 as *const () as usize).to_ne_bytes());
+    assert_eq!(unsafe { follow_jumps(plt1)? }, ret10 as *const ());
+    Ok(())
+  }
+
+  #[test]
+  fn follow_jumps_push_prolog() -> Result<()> {
+    // jmp +8; (padding); push -2; push 0; ret
+    let memory = executable_code(&[
+      0xE9, 0x08, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x6A, 0xFE,
+      0x6A, 0x00, 0xC3,
+    ])?;
+    let code = memory.as_ptr() as *const ();
+
+    // A prolog pushing immediates (e.g `__SEH_prolog4`) is not a PLT stub
+    let prolog = (code as usize + 13) as *const ();
+    assert_eq!(unsafe { follow_jumps(code)? }, prolog);
+    Ok(())
+  }
+}
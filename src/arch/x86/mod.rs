@@ -1,10 +1,12 @@
 pub use self::patcher::Patcher;
 pub use self::trampoline::Trampoline;
 
+pub mod context;
 pub mod meta;
 mod patcher;
 mod thunk;
 mod trampoline;
+pub mod variadic;
 
 // TODO: Add test for targets further away than DETOUR_RANGE
 // TODO: Add test for unsupported branches
@@ -78,6 +80,62 @@ mod tests {
     unsafe { detour_test(mem::transmute(hotpatch_ret0 as usize + 5), 0) }
   }
 
+  #[test]
+  fn analyze() -> Result<()> {
+    #[naked]
+    unsafe extern "C" fn no_patch_area() -> i32 {
+      asm!(
+        "
+            mov eax, 5
+            xor eax, eax
+            ret
+            mov eax, 5",
+        options(noreturn)
+      );
+    }
+
+    #[naked]
+    unsafe extern "C" fn hotpatch_ret0() -> i32 {
+      asm!(
+        "
+            nop
+            nop
+            nop
+            nop
+            nop
+            xor eax, eax
+            ret
+            mov eax, 5",
+        options(noreturn)
+      );
+    }
+
+    unsafe {
+      let target = (hotpatch_ret0 as usize + 5) as *const ();
+      let plan = crate::analyze(target)?;
+      assert_eq!(plan.strategy, crate::PatchStrategy::HotPatch);
+      assert_eq!(
+        plan.patch_area,
+        (target as usize - 5)..(target as usize + 2)
+      );
+      assert_eq!(
+        plan.original_bytes,
+        [0x90, 0x90, 0x90, 0x90, 0x90, 0x31, 0xC0]
+      );
+      assert!(!plan.requires_relay(ret10 as *const ()));
+
+      let target = (no_patch_area as usize + 5) as *const ();
+      assert_matches!(
+        crate::analyze(target),
+        Err(Error::NoPatchArea {
+          available: 3,
+          required: 5
+        })
+      );
+    }
+    Ok(())
+  }
+
   #[test]
   fn detour_padding_after() -> Result<()> {
     #[naked]
@@ -113,7 +171,13 @@ mod tests {
 
     let error =
       unsafe { RawDetour::new(external_loop as *const (), ret10 as *const ()) }.unwrap_err();
-    assert_matches!(error, Error::UnsupportedInstruction);
+    assert_matches!(error, Error::UnsupportedInstruction(ref instruction) if instruction.offset == 0);
+
+    assert!(error.to_string().contains("(target+0x0): e2 03 (loop "));
+    if let Error::UnsupportedInstruction(instruction) = error {
+      assert_eq!(instruction.mnemonic, "loop");
+      assert_eq!(instruction.bytes, [0xE2, 0x03]);
+    }
   }
 
   #[test]
@@ -153,6 +217,84 @@ mod tests {
     unsafe { detour_test(rip_relative_prolog_ret49, 49) }
   }
 
+  #[test]
+  fn follow_jumps() -> Result<()> {
+    #[naked]
+    unsafe extern "C" fn jmp_rel32() -> i32 {
+      asm!("jmp {}", sym ret10, options(noreturn));
+    }
+
+    #[naked]
+    unsafe extern "C" fn endbr_jmp_rel32() -> i32 {
+      asm!(
+        "
+            .byte 0xF3, 0x0F, 0x1E, 0xFA
+            jmp {}",
+        sym jmp_rel32,
+        options(noreturn)
+      );
+    }
+
+    unsafe {
+      let target = crate::follow_jumps(endbr_jmp_rel32 as *const ())?;
+      assert_eq!(target, ret10 as *const ());
+      assert_eq!(crate::follow_jumps(ret10 as *const ())?, ret10 as *const ());
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_arch = "x86_64")]
+  fn follow_jumps_indirect() -> Result<()> {
+    static SLOT: unsafe extern "C" fn() -> i32 = ret10;
+
+    #[naked]
+    unsafe extern "C" fn jmp_rip_relative() -> i32 {
+      asm!("jmp qword ptr [rip + {}]", sym SLOT, options(noreturn));
+    }
+
+    unsafe {
+      let target = crate::follow_jumps(jmp_rip_relative as *const ())?;
+      assert_eq!(target, ret10 as *const ());
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn mid_hook() -> Result<()> {
+    #[naked]
+    unsafe extern "C" fn add_constant() -> i32 {
+      asm!(
+        "
+            mov eax, 1
+            add eax, 0x100
+            ret",
+        options(noreturn)
+      );
+    }
+
+    unsafe {
+      let hook = crate::MidHook::new((add_constant as usize + 5) as *const (), |context| {
+        cfg_if::cfg_if! {
+          if #[cfg(target_arch = "x86_64")] {
+            assert_eq!(context.rax as u32, 1);
+            context.rax = 2;
+          } else {
+            assert_eq!(context.eax, 1);
+            context.eax = 2;
+          }
+        }
+      })?;
+
+      assert_eq!(add_constant(), 0x101);
+      hook.enable()?;
+      assert_eq!(add_constant(), 0x102);
+      hook.disable()?;
+      assert_eq!(add_constant(), 0x101);
+    }
+    Ok(())
+  }
+
   /// Default detour target.
   unsafe extern "C" fn ret10() -> i32 {
     10
@@ -1,7 +1,8 @@
 use super::thunk;
 use crate::error::{Error, Result};
-use crate::{pic, util};
-use std::{mem, slice};
+use crate::{pic, thread, util};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{mem, ptr, slice};
 
 pub struct Patcher {
   patch_area: &'static mut [u8],
@@ -32,26 +33,114 @@ impl Patcher {
     })
   }
 
+  /// Changes the address the target is redirected to.
+  pub fn set_destination(&mut self, detour: *const ()) {
+    let emitter = Self::hook_template(detour, self.patch_area);
+    self.detour_prolog = emitter.emit(self.patch_area.as_ptr() as *const ());
+  }
+
   /// Returns the target's patch area.
   pub fn area(&self) -> &[u8] {
     self.patch_area
   }
 
+  /// Returns the original bytes of the patch area.
+  pub fn original_prolog(&self) -> &[u8] {
+    &self.original_prolog
+  }
+
+  /// Returns the bytes written to the patch area when enabled.
+  pub fn detour_prolog(&self) -> &[u8] {
+    &self.detour_prolog
+  }
+
   /// Either patches or unpatches the function.
-  pub unsafe fn toggle(&mut self, enable: bool) {
-    // Copy either the detour or the original bytes of the function
-    self.patch_area.copy_from_slice(if enable {
+  ///
+  /// Other processors may be executing the target concurrently, so the code
+  /// is replaced without ever exposing a partially written instruction. Any
+  /// thread that reaches the target during the modification is redirected to
+  /// `trampoline`, if a breakpoint handler is supplied.
+  pub unsafe fn toggle(
+    &mut self,
+    enable: bool,
+    trampoline: *const (),
+    breakpoint: Option<&thread::Breakpoint>,
+  ) {
+    let code = if enable {
       &self.detour_prolog
     } else {
       &self.original_prolog
-    });
+    };
+
+    // A hot patch's long jump can only be reached through its short jump (at
+    // the target), so it must be written before, or removed after, the entry.
+    let jump_rel32_size = mem::size_of::<thunk::x86::JumpRel>();
+    let uses_hot_patch = self.patch_area.len() > jump_rel32_size;
+    let entry = if uses_hot_patch { jump_rel32_size } else { 0 };
+    let (head, tail) = self.patch_area.split_at_mut(entry);
+    let (code_head, code_tail) = code.split_at(entry);
+
+    if enable {
+      head.copy_from_slice(code_head);
+      thread::serialize();
+      Self::write_entry(tail, code_tail, trampoline, breakpoint);
+    } else {
+      Self::write_entry(tail, code_tail, trampoline, breakpoint);
+      head.copy_from_slice(code_head);
+      thread::serialize();
+    }
+  }
+
+  /// Replaces the instructions at a function's entry.
+  ///
+  /// If the code fits within an aligned 8-byte word, it's written with a
+  /// single atomic store. Otherwise the first byte is replaced with an `int3`
+  /// (trapping any thread entering the code), before the remaining bytes are
+  /// written, and lastly the first byte, with all processors being serialized
+  /// in between (i.e the `text_poke_bp` protocol).
+  unsafe fn write_entry(
+    area: &mut [u8],
+    code: &[u8],
+    redirect: *const (),
+    breakpoint: Option<&thread::Breakpoint>,
+  ) {
+    const INT3: u8 = 0xCC;
+    const WORD_SIZE: usize = mem::size_of::<u64>();
+
+    let address = area.as_ptr() as usize;
+    let word = address & !(WORD_SIZE - 1);
+
+    if address + area.len() <= word + WORD_SIZE {
+      let word = &*(word as *const AtomicU64);
+      let offset = address & (WORD_SIZE - 1);
+
+      let mut bytes = word.load(Ordering::SeqCst).to_ne_bytes();
+      bytes[offset..offset + code.len()].copy_from_slice(code);
+      word.store(u64::from_ne_bytes(bytes), Ordering::SeqCst);
+    } else if let Some(breakpoint) = breakpoint {
+      breakpoint.arm(area.as_ptr(), redirect);
+      ptr::write_volatile(&mut area[0], INT3);
+      thread::serialize();
+      area[1..].copy_from_slice(&code[1..]);
+      thread::serialize();
+      ptr::write_volatile(&mut area[0], code[0]);
+      breakpoint.disarm();
+    } else {
+      area.copy_from_slice(code);
+    }
+
+    thread::serialize();
   }
 
   /// Returns the patch area for a function, consisting of a long jump and
   /// possibly a short jump.
-  unsafe fn patch_area(target: *const (), prolog_size: usize) -> Result<&'static mut [u8]> {
+  pub unsafe fn patch_area(target: *const (), prolog_size: usize) -> Result<&'static mut [u8]> {
     let jump_rel08_size = mem::size_of::<thunk::x86::JumpShort>();
     let jump_rel32_size = mem::size_of::<thunk::x86::JumpRel>();
+    let no_patch_area = Error::NoPatchArea {
+      available: prolog_size,
+      required: jump_rel32_size,
+    };
 
     // Check if there isn't enough space for a relative long jump
     if !Self::is_patchable(target, prolog_size, jump_rel32_size) {
@@ -66,14 +155,14 @@ impl Patcher {
         if !Self::is_code_padding(hot_patch_area)
           || !util::is_executable_address(hot_patch_area.as_ptr() as *const _)?
         {
-          Err(Error::NoPatchArea)?;
+          Err(no_patch_area)?;
         }
 
         // The range is from the start of the hot patch to the end of the jump
         let patch_size = jump_rel32_size + jump_rel08_size;
         Ok(slice::from_raw_parts_mut(hot_patch as *mut u8, patch_size))
       } else {
-        Err(Error::NoPatchArea)
+        Err(no_patch_area)
       }
     } else {
       // The range is from the start of the function to the end of the jump
@@ -1,4 +1,6 @@
 //! The underlying disassembler should be opaque to the outside.
+use crate::error::FaultingInstruction;
+use std::ffi::CStr;
 use std::slice;
 
 /// A x86/x64 disassembler.
@@ -87,6 +89,22 @@ impl Instruction {
     }
   }
 
+  /// Returns the instruction's absolute memory operand, if applicable.
+  pub fn absolute_memory_operand(&self) -> Option<usize> {
+    unsafe {
+      // The operands address (e.g `jmp [0x401000]` ⟶ 0x401000)
+      self
+        .operands
+        .iter()
+        .find(|op| {
+          op.otype == udis::ud_type::UD_OP_MEM
+            && op.base == udis::ud_type::UD_NONE
+            && op.index == udis::ud_type::UD_NONE
+        })
+        .map(|op| op.lval.udword as usize)
+    }
+  }
+
   /// Returns true if this instruction any type of a loop.
   pub fn is_loop(&self) -> bool {
     match self.mnemonic {
@@ -109,6 +127,24 @@ impl Instruction {
     self.mnemonic == udis::ud_mnemonic_code::UD_Icall
   }
 
+  /// Returns true if this instruction pushes an immediate value.
+  pub fn is_push_immediate(&self) -> bool {
+    self.mnemonic == udis::ud_mnemonic_code::UD_Ipush
+      && self
+        .operands
+        .first()
+        .map_or(false, |op| op.otype == udis::ud_type::UD_OP_IMM)
+  }
+
+  /// Returns true if this instruction pushes a value from memory.
+  pub fn is_push_memory(&self) -> bool {
+    self.mnemonic == udis::ud_mnemonic_code::UD_Ipush
+      && self
+        .operands
+        .first()
+        .map_or(false, |op| op.otype == udis::ud_type::UD_OP_MEM)
+  }
+
   /// Returns true if this instruction is a return.
   pub fn is_return(&self) -> bool {
     self.mnemonic == udis::ud_mnemonic_code::UD_Iret
@@ -124,3 +160,32 @@ impl Instruction {
     self.bytes.len()
   }
 }
+
+/// Describes the instruction at an address, for use in an error.
+///
+/// If the instruction cannot be decoded, only its first byte is included.
+pub unsafe fn describe(target: *const (), address: usize) -> FaultingInstruction {
+  let mut disassembler = Disassembler::new(address as *const ());
+  udis::ud_set_syntax(&mut disassembler.0, Some(udis::ud_translate_intel));
+
+  let length = udis::ud_disassemble(&mut disassembler.0) as usize;
+  let (mnemonic, disassembly) = if length > 0 {
+    let mnemonic = udis::ud_lookup_mnemonic(udis::ud_insn_mnemonic(&disassembler.0));
+    (
+      CStr::from_ptr(mnemonic).to_string_lossy().into_owned(),
+      CStr::from_ptr(udis::ud_insn_asm(&disassembler.0))
+        .to_string_lossy()
+        .into_owned(),
+    )
+  } else {
+    ("invalid".to_string(), "invalid".to_string())
+  };
+
+  FaultingInstruction {
+    address,
+    offset: address.wrapping_sub(target as usize),
+    bytes: slice::from_raw_parts(address as *const u8, length.max(1)).to_vec(),
+    mnemonic,
+    disassembly,
+  }
+}
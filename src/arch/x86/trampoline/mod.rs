@@ -3,13 +3,16 @@ use crate::arch::x86::thunk;
 use crate::error::{Error, Result};
 use crate::pic;
 use std::mem;
+use std::ops::Range;
 
-mod disasm;
+pub mod disasm;
 
 /// A trampoline generator (x86/x64).
 pub struct Trampoline {
   emitter: pic::CodeEmitter,
   prolog_size: usize,
+  instruction_offsets: Vec<(usize, usize)>,
+  continuations: Vec<(Range<usize>, usize)>,
 }
 
 impl Trampoline {
@@ -27,6 +30,18 @@ impl Trampoline {
   pub fn prolog_size(&self) -> usize {
     self.prolog_size
   }
+
+  /// Returns the offset of each prolog instruction, both in the target and
+  /// in the trampoline.
+  pub fn instruction_offsets(&self) -> &[(usize, usize)] {
+    &self.instruction_offsets
+  }
+
+  /// Returns where a thread within each part of the trampoline continues, as
+  /// an offset in the target. Together, the parts span the whole trampoline.
+  pub fn continuations(&self) -> &[(Range<usize>, usize)] {
+    &self.continuations
+  }
 }
 
 /// A trampoline builder.
@@ -63,16 +78,35 @@ impl Builder {
   /// Margins larger than five bytes may lead to undefined behavior.
   pub unsafe fn build(mut self) -> Result<Trampoline> {
     let mut emitter = pic::CodeEmitter::new();
+    let mut instruction_offsets = Vec::new();
+    let mut continuations = Vec::new();
 
     while !self.finished {
       let instruction = self.next_instruction()?;
       let thunk = self.process_instruction(&instruction)?;
 
+      // Keep track of where each instruction is relocated to
+      let offset = instruction.address() - self.target as usize;
+      let start = emitter.len();
+      instruction_offsets.push((offset, start));
+
+      // A thread within a thunk has yet to complete the instruction, unless it
+      // has returned from a relocated call. Any other thunk only branches.
+      if instruction.is_call() {
+        continuations.push((start..start + 1, offset));
+        continuations.push((start + 1..start + thunk.len(), offset + instruction.len()));
+      } else {
+        continuations.push((start..start + thunk.len(), offset));
+      }
+
       // If the trampoline displacement is larger than the target
       // function, all instructions will be displaced, and if there is
       // internal branching, it will end up at the wrong instructions.
       if self.is_instruction_in_branch(&instruction) && instruction.len() != thunk.len() {
-        Err(Error::UnsupportedInstruction)?;
+        Err(Error::UnsupportedInstruction(describe(
+          self.target,
+          instruction.address(),
+        )))?;
       } else {
         emitter.add_thunk(thunk);
       }
@@ -80,13 +114,17 @@ impl Builder {
       // Determine whether enough bytes for the margin has been disassembled
       if self.total_bytes_disassembled >= self.margin && !self.finished {
         // Add a jump to the first instruction after the prolog
+        let start = emitter.len();
         emitter.add_thunk(thunk::jmp(instruction.next_instruction_address()));
+        continuations.push((start..emitter.len(), self.total_bytes_disassembled));
         self.finished = true;
       }
     }
 
     Ok(Trampoline {
       prolog_size: self.total_bytes_disassembled,
+      instruction_offsets,
+      continuations,
       emitter,
     })
   }
@@ -97,7 +135,10 @@ impl Builder {
 
     // Disassemble the next instruction
     match Instruction::new(&mut self.disassembler, instruction_address as *const _) {
-      None => Err(Error::InvalidCode)?,
+      None => Err(Error::InvalidCode(describe(
+        self.target,
+        instruction_address,
+      )))?,
       Some(instruction) => {
         // Keep track of the total amount of bytes
         self.total_bytes_disassembled += instruction.len();
@@ -202,7 +243,10 @@ impl Builder {
       Ok(Box::new(instruction.as_slice().to_vec()))
     } else if instruction.is_loop() {
       // Loops (e.g 'loopnz', 'jecxz') to the outside are not supported
-      Err(Error::UnsupportedInstruction)
+      Err(Error::UnsupportedInstruction(describe(
+        self.target,
+        instruction.address(),
+      )))
     } else if instruction.is_unconditional_jump() {
       // If the function is not in a branch, and it unconditionally jumps
       // a distance larger than the prolog, it's the same as if it terminates.
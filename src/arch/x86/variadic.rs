@@ -0,0 +1,274 @@
+use crate::pic;
+use std::{mem, ptr};
+
+/// The amount of stack words captured, following the return address.
+const STACK_WORDS: usize = 32;
+
+cfg_if::cfg_if! {
+  if #[cfg(target_arch = "x86_64")] {
+    /// The arguments of a call, as passed by its caller (x64).
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Arguments {
+      xmm: [[u64; 2]; 8],
+      /// The registers `rdi`, `rsi`, `rdx`, `rcx`, `r8`, `r9`, `rax` & `r10`.
+      registers: [u64; 8],
+      stack: [u64; STACK_WORDS],
+    }
+
+    /// The offset of the general-purpose registers.
+    const REGISTERS_OFFSET: usize = 128;
+
+    /// The offset of the stack words.
+    const STACK_OFFSET: usize = 192;
+
+    impl Arguments {
+      /// Creates an empty set of arguments.
+      pub const fn new() -> Self {
+        Arguments {
+          xmm: [[0; 2]; 8],
+          registers: [0; 8],
+          stack: [0; STACK_WORDS],
+        }
+      }
+
+      /// Captures the arguments saved by a selector stub.
+      pub unsafe fn capture(stack: usize) -> Self {
+        let read = |offset: usize| ptr::read_unaligned((stack + offset) as *const u64);
+
+        let mut arguments = Self::new();
+        for (index, xmm) in arguments.xmm.iter_mut().enumerate() {
+          *xmm = [read(index * 16), read(index * 16 + 8)];
+        }
+
+        // The registers are pushed in reverse, after the XMM area
+        let (rdi, rsi, rdx, rcx, r8, r9, rax, r10) =
+          (read(160), read(168), read(176), read(184), read(152), read(144), read(192), read(136));
+        arguments.registers = [rdi, rsi, rdx, rcx, r8, r9, rax, r10];
+
+        // The stack arguments follow the return address
+        for (index, word) in arguments.stack.iter_mut().enumerate() {
+          *word = read(208 + index * 8);
+        }
+        arguments
+      }
+    }
+
+    impl Cursor {
+      /// Returns the offset of the next argument, and advances the cursor.
+      pub fn next(&mut self, size: usize, float: bool) -> usize {
+        assert!(size <= 8, "unsupported variadic argument size");
+
+        if cfg!(windows) {
+          // Each argument occupies a slot, and the first four are passed in
+          // registers (the stack area reserved for them is captured as well).
+          let index = self.general;
+          self.general += 1;
+
+          match index {
+            0..=3 if float => index * 16,
+            0..=3 => REGISTERS_OFFSET + [3, 2, 4, 5][index] * 8,
+            _ => self.stack_offset(index, 1),
+          }
+        } else if float && self.vector < 8 {
+          self.vector += 1;
+          (self.vector - 1) * 16
+        } else if !float && self.general < 6 {
+          self.general += 1;
+          REGISTERS_OFFSET + (self.general - 1) * 8
+        } else {
+          let index = self.stack;
+          self.stack += 1;
+          self.stack_offset(index, 1)
+        }
+      }
+    }
+
+    /// Creates a function, `forward(&arguments, target)`, which calls the
+    /// target with the captured arguments, and returns its result.
+    pub fn forwarder_builder() -> pic::CodeEmitter {
+      let mut code = vec![
+        0x55,             // push rbp
+        0x48, 0x89, 0xE5, // mov rbp, rsp
+        0x53,             // push rbx
+        0x56,             // push rsi
+        0x57,             // push rdi
+      ];
+
+      if cfg!(windows) {
+        code.extend_from_slice(&[0x48, 0x89, 0xCB]); // mov rbx, rcx
+        code.extend_from_slice(&[0x49, 0x89, 0xD3]); // mov r11, rdx
+      } else {
+        code.extend_from_slice(&[0x48, 0x89, 0xFB]); // mov rbx, rdi
+        code.extend_from_slice(&[0x49, 0x89, 0xF3]); // mov r11, rsi
+      }
+
+      // The stack is aligned once the words (with their padding) are allocated
+      code.extend_from_slice(&[0x48, 0x81, 0xEC]); // sub rsp, size
+      code.extend_from_slice(&((STACK_WORDS * 8 + 8) as u32).to_le_bytes());
+      code.extend_from_slice(&[0x48, 0x8D, 0xB3]); // lea rsi, [rbx+offset]
+      code.extend_from_slice(&(STACK_OFFSET as u32).to_le_bytes());
+      code.extend_from_slice(&[0x48, 0x89, 0xE7]); // mov rdi, rsp
+      code.push(0xB9); // mov ecx, words
+      code.extend_from_slice(&(STACK_WORDS as u32).to_le_bytes());
+      code.extend_from_slice(&[0xFC, 0xF3, 0x48, 0xA5]); // cld; rep movsq
+
+      // Only the first four XMM registers are volatile on Windows
+      for register in 0..if cfg!(windows) { 4 } else { 8 } {
+        // movups xmm, [rbx+offset]
+        code.extend_from_slice(&[0x0F, 0x10, 0x43 | (register << 3), register * 16]);
+      }
+
+      // mov register, [rbx+offset]
+      let registers = [
+        [0x48, 0x8B, 0xBB], // rdi
+        [0x48, 0x8B, 0xB3], // rsi
+        [0x48, 0x8B, 0x93], // rdx
+        [0x48, 0x8B, 0x8B], // rcx
+        [0x4C, 0x8B, 0x83], // r8
+        [0x4C, 0x8B, 0x8B], // r9
+        [0x48, 0x8B, 0x83], // rax (vector register count)
+        [0x4C, 0x8B, 0x93], // r10
+      ];
+      for (index, register) in registers.iter().enumerate() {
+        code.extend_from_slice(register);
+        code.extend_from_slice(&((REGISTERS_OFFSET + index * 8) as u32).to_le_bytes());
+      }
+
+      code.extend_from_slice(&[
+        0x41, 0xFF, 0xD3,       // call r11
+        0x48, 0x8D, 0x65, 0xE8, // lea rsp, [rbp-24]
+        0x5F,                   // pop rdi
+        0x5E,                   // pop rsi
+        0x5B,                   // pop rbx
+        0x5D,                   // pop rbp
+        0xC3,                   // ret
+      ]);
+
+      let mut emitter = pic::CodeEmitter::new();
+      emitter.add_thunk(Box::new(code));
+      emitter
+    }
+  } else {
+    /// The arguments of a call, as passed by its caller (x86).
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Arguments {
+      /// The registers `eax`, `ecx` & `edx`.
+      registers: [u32; 3],
+      stack: [u32; STACK_WORDS],
+    }
+
+    /// The offset of the stack words.
+    const STACK_OFFSET: usize = 12;
+
+    impl Arguments {
+      /// Creates an empty set of arguments.
+      pub const fn new() -> Self {
+        Arguments {
+          registers: [0; 3],
+          stack: [0; STACK_WORDS],
+        }
+      }
+
+      /// Captures the arguments saved by a selector stub.
+      pub unsafe fn capture(stack: usize) -> Self {
+        // The selector passes the address of the saved registers
+        let frame = *(stack as *const usize);
+        let read = |offset: usize| ptr::read_unaligned((frame + offset) as *const u32);
+
+        let mut arguments = Self::new();
+        arguments.registers = [read(8), read(4), read(0)];
+
+        // The stack arguments follow the return address
+        for (index, word) in arguments.stack.iter_mut().enumerate() {
+          *word = read(16 + index * 4);
+        }
+        arguments
+      }
+    }
+
+    impl Cursor {
+      /// Returns the offset of the next argument, and advances the cursor.
+      pub fn next(&mut self, size: usize, _float: bool) -> usize {
+        // All arguments are passed on the stack
+        let words = (size + 3) / 4;
+        let index = self.stack;
+        self.stack += words;
+        self.stack_offset(index, words)
+      }
+    }
+
+    /// Creates a function, `forward(&arguments, target)`, which calls the
+    /// target with the captured arguments, and returns its result.
+    pub fn forwarder_builder() -> pic::CodeEmitter {
+      let mut code = vec![
+        0x55,             // push ebp
+        0x89, 0xE5,       // mov ebp, esp
+        0x56,             // push esi
+        0x57,             // push edi
+        0x53,             // push ebx
+        0x8B, 0x5D, 0x08, // mov ebx, [ebp+8]
+        0x81, 0xEC,       // sub esp, size
+      ];
+      code.extend_from_slice(&((STACK_WORDS * 4) as u32).to_le_bytes());
+      code.extend_from_slice(&[
+        0x83, 0xE4, 0xF0, // and esp, -16
+        0x8D, 0x73,       // lea esi, [ebx+offset]
+        STACK_OFFSET as u8,
+        0x89, 0xE7,       // mov edi, esp
+        0xB9,             // mov ecx, words
+      ]);
+      code.extend_from_slice(&(STACK_WORDS as u32).to_le_bytes());
+      code.extend_from_slice(&[
+        0xFC,             // cld
+        0xF3, 0xA5,       // rep movsd
+        0x8B, 0x03,       // mov eax, [ebx]
+        0x8B, 0x4B, 0x04, // mov ecx, [ebx+4]
+        0x8B, 0x53, 0x08, // mov edx, [ebx+8]
+        0xFF, 0x55, 0x0C, // call [ebp+12]
+        0x8D, 0x65, 0xF4, // lea esp, [ebp-12]
+        0x5B,             // pop ebx
+        0x5F,             // pop edi
+        0x5E,             // pop esi
+        0x5D,             // pop ebp
+        0xC3,             // ret
+      ]);
+
+      let mut emitter = pic::CodeEmitter::new();
+      emitter.add_thunk(Box::new(code));
+      emitter
+    }
+  }
+}
+
+/// The position of the next argument, within a set of arguments.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Cursor {
+  general: usize,
+  vector: usize,
+  stack: usize,
+}
+
+impl Cursor {
+  /// Returns the offset of a stack argument, if it has been captured.
+  fn stack_offset(&self, index: usize, words: usize) -> usize {
+    assert!(
+      index + words <= STACK_WORDS,
+      "variadic argument beyond the captured stack"
+    );
+    STACK_OFFSET + index * mem::size_of::<usize>()
+  }
+}
+
+impl Arguments {
+  /// Reads an argument at an offset.
+  pub unsafe fn read<T: Copy>(&self, offset: usize) -> T {
+    ptr::read_unaligned((self as *const Self as *const u8).add(offset) as *const T)
+  }
+
+  /// Writes an argument at an offset.
+  pub unsafe fn write<T>(&mut self, offset: usize, value: T) {
+    ptr::write_unaligned((self as *mut Self as *mut u8).add(offset) as *mut T, value)
+  }
+}
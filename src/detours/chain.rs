@@ -0,0 +1,294 @@
+use crate::arch::Detour;
+use crate::error::Result;
+use crate::info::DetourInfo;
+use crate::panic::PanicPolicy;
+use crate::traits::private;
+use crate::{Function, GenericDetour};
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::{fmt, ptr};
+
+/// An ordered chain of detours, sharing a single hook of a target.
+///
+/// The target is patched once, and each call passes through the chain's
+/// links, from the highest priority to the lowest. Every link receives a
+/// [Next](./struct.Next.html) handle, which calls the following link, or the
+/// original function once the end of the chain is reached. Links may be
+/// inserted and removed at any time, without the target being patched again.
+///
+/// Due to being generated by a macro, the `HookChain::new`,
+/// `HookChain::insert` and `Next::call` methods are not exposed in the
+/// documentation:
+///
+/// ```c
+/// /// Create a new chain given a target function. Its hook is disabled, and
+/// /// it has no links.
+/// unsafe fn new(target: T) -> Result<Self>
+///
+/// /// Inserts a link, after any links with a higher or equal priority.
+/// fn insert<C>(&self, priority: i32, closure: C) -> LinkId
+///   where C: Fn(&Next<T>, T::Arguments) -> T::Output + Send + Sync + 'static
+///
+/// /// Calls the following link, or the original function.
+/// fn call(&self, T::Arguments) -> T::Output
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::HookChain;
+///
+/// #[inline(never)]
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let chain = unsafe { HookChain::<fn(i32) -> i32>::new(add5)? };
+/// unsafe { chain.enable()? };
+///
+/// let double = chain.insert(0, |next, val| next.call(val) * 2);
+/// chain.insert(10, |next, val| next.call(val + 1));
+///
+/// // The links run in order of priority, before the original function
+/// assert_eq!(add5(1), 14);
+///
+/// chain.remove(double);
+/// assert_eq!(add5(1), 7);
+/// # Ok(())
+/// # }
+/// ```
+pub struct HookChain<T: Function> {
+  detour: GenericDetour<T>,
+  state: Arc<ChainState<T>>,
+}
+
+/// The identifier of a link within a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkId(usize);
+
+/// A link's closure, receiving the arguments as a tuple.
+pub(crate) type LinkClosure<T> =
+  dyn Fn(&Next<T>, <T as Function>::Arguments) -> <T as Function>::Output + Send + Sync;
+
+/// A function calling a trampoline, with the arguments as a tuple.
+pub(crate) type CallOriginal<T> =
+  fn(*const (), <T as Function>::Arguments) -> <T as Function>::Output;
+
+/// A link within a chain.
+pub(crate) struct Link<T: Function> {
+  id: usize,
+  priority: i32,
+  closure: Arc<LinkClosure<T>>,
+}
+
+impl<T: Function> Clone for Link<T> {
+  fn clone(&self) -> Self {
+    Link {
+      id: self.id,
+      priority: self.priority,
+      closure: self.closure.clone(),
+    }
+  }
+}
+
+/// The links of a chain, shared with its dispatching closure.
+pub(crate) struct ChainState<T: Function> {
+  /// The links, ordered by priority. Calls in progress keep a reference to
+  /// the links they started with, so the links are replaced rather than
+  /// modified.
+  links: RwLock<Arc<Vec<Link<T>>>>,
+  next_id: AtomicUsize,
+  trampoline: AtomicPtr<()>,
+  call_original: CallOriginal<T>,
+}
+
+impl<T: Function> ChainState<T> {
+  /// Creates an empty chain state.
+  pub(crate) fn new(call_original: CallOriginal<T>) -> Arc<Self> {
+    Arc::new(ChainState {
+      links: RwLock::new(Arc::new(Vec::new())),
+      next_id: AtomicUsize::new(0),
+      trampoline: AtomicPtr::new(ptr::null_mut()),
+      call_original,
+    })
+  }
+
+  /// Passes a call to the target through the chain.
+  pub(crate) fn dispatch(&self, arguments: T::Arguments) -> T::Output {
+    let links = self.links.read().unwrap().clone();
+    Next {
+      links: &links,
+      state: self,
+    }
+    .invoke(arguments)
+  }
+}
+
+impl<T: Function> HookChain<T> {
+  /// Creates a chain from its hook, and the state shared with the hook's
+  /// closure.
+  pub(crate) fn from_parts(detour: GenericDetour<T>, state: Arc<ChainState<T>>) -> Self {
+    let trampoline = detour.trampoline() as *const () as *mut ();
+    state.trampoline.store(trampoline, Ordering::SeqCst);
+    HookChain { detour, state }
+  }
+
+  /// Inserts a link with a boxed closure.
+  pub(crate) fn insert_boxed(&self, priority: i32, closure: Box<LinkClosure<T>>) -> LinkId {
+    let id = self.state.next_id.fetch_add(1, Ordering::SeqCst);
+    let link = Link {
+      closure: Arc::from(closure),
+      priority,
+      id,
+    };
+
+    self.modify(|links| {
+      let index = links
+        .iter()
+        .position(|other| other.priority < priority)
+        .unwrap_or(links.len());
+      links.insert(index, link);
+    });
+    LinkId(id)
+  }
+
+  /// Removes a link, returning whether it was part of the chain.
+  ///
+  /// Calls in progress keep a reference to the link, which is released once
+  /// the last of them returns.
+  pub fn remove(&self, link: LinkId) -> bool {
+    let mut removed = false;
+    self.modify(|links| {
+      let length = links.len();
+      links.retain(|other| other.id != link.0);
+      removed = links.len() != length;
+    });
+    removed
+  }
+
+  /// Changes the priority of a link, returning whether it was part of the
+  /// chain.
+  ///
+  /// The link is moved after any links with a higher or equal priority.
+  pub fn set_priority(&self, link: LinkId, priority: i32) -> bool {
+    let mut found = false;
+    self.modify(|links| {
+      if let Some(index) = links.iter().position(|other| other.id == link.0) {
+        let mut link = links.remove(index);
+        link.priority = priority;
+
+        let index = links
+          .iter()
+          .position(|other| other.priority < priority)
+          .unwrap_or(links.len());
+        links.insert(index, link);
+        found = true;
+      }
+    });
+    found
+  }
+
+  /// Returns the amount of links in the chain.
+  pub fn len(&self) -> usize {
+    self.state.links.read().unwrap().len()
+  }
+
+  /// Returns whether the chain has no links.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Enables the chain's hook.
+  pub unsafe fn enable(&self) -> Result<()> {
+    self.detour.enable()
+  }
+
+  /// Disables the chain's hook.
+  pub unsafe fn disable(&self) -> Result<()> {
+    self.detour.disable()
+  }
+
+  /// Returns whether the chain's hook is enabled or not.
+  pub fn is_enabled(&self) -> bool {
+    self.detour.is_enabled()
+  }
+
+  /// Returns the address of the patched code.
+  pub fn target(&self) -> *const () {
+    self.detour.target()
+  }
+
+  /// Returns a description of how the hook is applied.
+  pub fn info(&self) -> DetourInfo {
+    self.detour.info()
+  }
+
+  /// Sets how a panic within any of the links is handled (aborting by
+  /// default).
+  pub fn set_panic_policy(&self, policy: PanicPolicy<T::Output>) {
+    self.detour.set_panic_policy(policy);
+  }
+
+  /// Replaces the links with a modified copy of them.
+  fn modify<F: FnOnce(&mut Vec<Link<T>>)>(&self, modify: F) {
+    let mut links = self.state.links.write().unwrap();
+    let mut copy = Vec::clone(&links);
+    modify(&mut copy);
+    *links = Arc::new(copy);
+  }
+}
+
+impl<T: Function> private::Sealed for HookChain<T> {
+  fn base(&self) -> Result<&Detour> {
+    self.detour.base()
+  }
+}
+
+impl<T: Function> fmt::Debug for HookChain<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "HookChain {{ enabled: {}, links: {} }}",
+      self.is_enabled(),
+      self.len()
+    )
+  }
+}
+
+/// A handle to the remainder of a chain, passed to each of its links.
+pub struct Next<'a, T: Function> {
+  links: &'a [Link<T>],
+  state: &'a ChainState<T>,
+}
+
+impl<'a, T: Function> Next<'a, T> {
+  /// Calls the following link, or the original function.
+  pub(crate) fn invoke(&self, arguments: T::Arguments) -> T::Output {
+    match self.links.split_first() {
+      Some((link, links)) => {
+        let next = Next {
+          state: self.state,
+          links,
+        };
+        (link.closure)(&next, arguments)
+      },
+      None => {
+        let trampoline = self.state.trampoline.load(Ordering::SeqCst);
+        (self.state.call_original)(trampoline, arguments)
+      },
+    }
+  }
+
+  /// Returns the amount of links remaining, before the original function.
+  pub fn remaining(&self) -> usize {
+    self.links.len()
+  }
+}
+
+impl<'a, T: Function> fmt::Debug for Next<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Next {{ remaining: {} }}", self.remaining())
+  }
+}
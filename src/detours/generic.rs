@@ -1,7 +1,23 @@
-use crate::arch::Detour;
+use crate::alloc::ExecutableMemory;
+use crate::arch::{self, memory, Detour};
+use crate::detours::EnableGuard;
 use crate::error::Result;
+use crate::info::DetourInfo;
+use crate::panic::{Panic, PanicPolicy};
+use crate::reentrancy::{self, ReentrancyGuard};
+use crate::traits::private;
+use crate::variadic;
 use crate::{Function, HookableWith};
+use std::cell::Cell;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::{fmt, ptr};
+
+thread_local! {
+  /// The closure thunk the current thread is passing through.
+  static THUNK: Cell<*const ()> = const { Cell::new(ptr::null()) };
+}
 
 /// A type-safe detour.
 ///
@@ -14,6 +30,24 @@ use std::marker::PhantomData;
 /// fn call(&self, T::Arguments) -> T::Output
 /// ```
 ///
+/// The same applies to `GenericDetour::with_closure`, which uses a closure as
+/// the detour, without requiring any `static`:
+///
+/// ```c
+/// /// Create a new hook given a target function and a compatible detour
+/// /// closure.
+/// ///
+/// /// A thunk, forwarding calls to the closure, is allocated for each hook.
+/// unsafe fn with_closure<C>(target: T, closure: C) -> Result<Self>
+///   where C: Fn<T::Arguments, Output = T::Output> + Send + Sync + 'static;
+/// ```
+///
+/// C variadic functions (e.g `extern "C" fn(*const c_char, ...) -> c_int`)
+/// can be detoured as well. Their closures, and `call`, accept the fixed
+/// arguments followed by [VariadicArgs](./struct.VariadicArgs.html), which
+/// `call` forwards to the original function unchanged. Alternatively, a
+/// function defined using `c_variadic` can be used as the detour.
+///
 /// # Example
 ///
 /// ```rust
@@ -49,6 +83,9 @@ use std::marker::PhantomData;
 pub struct GenericDetour<T: Function> {
   phantom: PhantomData<T>,
   detour: Detour,
+  // Dropped after the detour has been disabled
+  #[allow(dead_code)]
+  thunk: Option<ClosureThunk<T>>,
 }
 
 impl<T: Function> GenericDetour<T> {
@@ -62,9 +99,76 @@ impl<T: Function> GenericDetour<T> {
     Detour::new(target.to_ptr(), detour.to_ptr()).map(|detour| GenericDetour {
       phantom: PhantomData,
       detour,
+      thunk: None,
     })
   }
 
+  /// Create a new hook given a target function and a closure, invoked by a
+  /// thunk through `shim`.
+  ///
+  /// If the target is variadic, the thunk captures its arguments as well.
+  pub(crate) unsafe fn with_boxed_closure(
+    target: T,
+    closure: Box<T::Closure>,
+    shim: *const (),
+    variadic: bool,
+  ) -> Result<Self> {
+    if variadic {
+      variadic::allocate_forwarder()?;
+    }
+
+    let mut context = Box::new(ClosureContext::<T> {
+      shim,
+      closure,
+      variadic,
+      target: target.to_ptr(),
+      trampoline: ptr::null(),
+      panic_policy: RwLock::new(None),
+      reentrancy_guard: AtomicBool::new(false),
+    });
+
+    let emitter = arch::selector_builder(
+      enter_thunk::<T> as *const () as usize,
+      &*context as *const ClosureContext<T> as usize,
+    );
+
+    let memory = {
+      let mut pool = memory::POOL.lock().unwrap();
+      memory::allocate_pic(&mut pool, &emitter, target.to_ptr())?
+    };
+
+    let detour = Detour::new(target.to_ptr(), memory.as_ptr() as *const ())?;
+    context.trampoline = detour.trampoline() as *const ();
+
+    Ok(GenericDetour {
+      phantom: PhantomData,
+      detour,
+      thunk: Some(ClosureThunk { memory, context }),
+    })
+  }
+
+  /// Returns the context of the thunk the current thread passed through.
+  ///
+  /// This must only be called by a shim, before anything else.
+  pub(crate) unsafe fn current_context<'a>() -> &'a ClosureContext<T> {
+    &*(THUNK.with(Cell::get) as *const ClosureContext<T>)
+  }
+
+  /// Create a new hook given a module's symbol and a compatible detour
+  /// function.
+  ///
+  /// The symbol is resolved like it is for
+  /// [RawDetour::from_symbol](./struct.RawDetour.html#method.from_symbol), and
+  /// must refer to a function of type `T`.
+  #[cfg(target_os = "linux")]
+  pub unsafe fn from_symbol<D>(module: &str, symbol: &str, detour: D) -> Result<Self>
+  where
+    T: HookableWith<D>,
+    D: Function,
+  {
+    Self::new(T::from_ptr(crate::elf::symbol(module, symbol)?), detour)
+  }
+
   /// Enables the detour.
   pub unsafe fn enable(&self) -> Result<()> {
     self.detour.enable()
@@ -75,16 +179,200 @@ impl<T: Function> GenericDetour<T> {
     self.detour.disable()
   }
 
+  /// Enables the detour until the returned guard is dropped.
+  ///
+  /// Scopes may overlap, in which case the detour remains enabled until the
+  /// last of them ends.
+  pub unsafe fn enable_scoped(&self) -> Result<EnableGuard<'_>> {
+    EnableGuard::new(&self.detour)
+  }
+
+  /// Invokes a closure whilst the detour is enabled.
+  ///
+  /// The detour is disabled afterwards, even if the closure panics.
+  pub unsafe fn with_enabled<F, R>(&self, closure: F) -> Result<R>
+  where
+    F: FnOnce() -> R,
+  {
+    let _guard = self.enable_scoped()?;
+    Ok(closure())
+  }
+
   /// Returns whether the detour is enabled or not.
   pub fn is_enabled(&self) -> bool {
     self.detour.is_enabled()
   }
 
+  /// Sets whether the detour only applies to threads which have opted in,
+  /// using `enable_for_current_thread` (disabled by default).
+  ///
+  /// Any other thread calls the original function, as if it wasn't hooked.
+  pub unsafe fn set_per_thread(&self, per_thread: bool) -> Result<()> {
+    self.detour.set_per_thread(per_thread)
+  }
+
+  /// Opts in the current thread, when the detour is applied per thread.
+  pub fn enable_for_current_thread(&self) -> Result<()> {
+    self.detour.enable_for_current_thread()
+  }
+
+  /// Opts out the current thread, when the detour is applied per thread.
+  pub fn disable_for_current_thread(&self) {
+    self.detour.disable_for_current_thread()
+  }
+
+  /// Returns whether the current thread has opted in.
+  pub fn is_enabled_for_current_thread(&self) -> bool {
+    self.detour.is_enabled_for_current_thread()
+  }
+
+  /// Returns the address of the patched code.
+  pub fn target(&self) -> *const () {
+    self.detour.target()
+  }
+
+  /// Returns a description of how the detour is applied.
+  pub fn info(&self) -> DetourInfo {
+    self.detour.info()
+  }
+
+  /// Assigns a label to the detour, identifying it within the
+  /// [registry](./registry/index.html).
+  pub fn set_label<S: Into<String>>(&self, label: S) {
+    self.detour.set_label(Some(label.into()));
+  }
+
+  /// Sets how a panic within the detour's closure is handled (aborting by
+  /// default).
+  ///
+  /// It only applies to detours created using `with_closure`.
+  pub fn set_panic_policy(&self, policy: PanicPolicy<T::Output>) {
+    if let Some(thunk) = &self.thunk {
+      *thunk.context.panic_policy.write().unwrap() = Some(Arc::new(policy));
+    }
+  }
+
+  /// Sets whether calls to the target, made by a thread already executing the
+  /// detour, are forwarded to the original function (disabled by default).
+  ///
+  /// It only applies to detours created using `with_closure`, since a detour
+  /// function is invoked directly; it can use `enter` instead.
+  pub fn set_reentrancy_guard(&self, enabled: bool) {
+    if let Some(thunk) = &self.thunk {
+      thunk
+        .context
+        .reentrancy_guard
+        .store(enabled, Ordering::SeqCst);
+    }
+  }
+
+  /// Marks the current thread as executing the detour, unless it already is.
+  ///
+  /// It's intended to be called within the detour, which may then forward
+  /// any nested calls (i.e when `None` is returned) to the original function.
+  pub fn enter(&self) -> Option<ReentrancyGuard> {
+    reentrancy::enter(self.target() as usize)
+  }
+
+  /// Returns whether the current thread is executing the detour, i.e whether
+  /// a call to the target would be nested.
+  pub fn is_nested(&self) -> bool {
+    reentrancy::is_executing(self.target() as usize)
+  }
+
   /// Returns a reference to the generated trampoline.
   pub(crate) fn trampoline(&self) -> &() {
     self.detour.trampoline()
   }
 }
 
+impl<T: Function> private::Sealed for GenericDetour<T> {
+  fn base(&self) -> Result<&Detour> {
+    Ok(&self.detour)
+  }
+}
+
 unsafe impl<T: Function> Send for GenericDetour<T> {}
 unsafe impl<T: Function> Sync for GenericDetour<T> {}
+
+/// A thunk, invoking a closure as a detour.
+struct ClosureThunk<T: Function> {
+  #[allow(dead_code)]
+  memory: ExecutableMemory,
+  context: Box<ClosureContext<T>>,
+}
+
+impl<T: Function> fmt::Debug for ClosureThunk<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ClosureThunk {{ shim: {:?} }}", self.context.shim)
+  }
+}
+
+/// The data a closure thunk passes to `enter_thunk`.
+pub(crate) struct ClosureContext<T: Function> {
+  shim: *const (),
+  closure: Box<T::Closure>,
+  variadic: bool,
+  target: *const (),
+  trampoline: *const (),
+  panic_policy: RwLock<Option<Arc<PanicPolicy<T::Output>>>>,
+  reentrancy_guard: AtomicBool,
+}
+
+impl<T: Function> ClosureContext<T> {
+  /// Returns the detour closure.
+  pub(crate) fn closure(&self) -> &T::Closure {
+    &self.closure
+  }
+
+  /// Returns the address of the trampoline.
+  pub(crate) fn trampoline(&self) -> *const () {
+    self.trampoline
+  }
+
+  /// Marks the current thread as executing the closure, if nested calls are
+  /// guarded against.
+  pub(crate) fn enter(&self) -> Option<ReentrancyGuard> {
+    if self.reentrancy_guard.load(Ordering::SeqCst) {
+      Some(reentrancy::enter_nested(self.target as usize))
+    } else {
+      None
+    }
+  }
+
+  /// Handles a panic caught within the detour closure.
+  pub(crate) fn recover<F>(&self, panic: Panic<T::Arguments>, original: F) -> T::Output
+  where
+    F: FnOnce(T::Arguments) -> T::Output,
+  {
+    let policy = self.panic_policy.read().unwrap().clone();
+    let hook = format!("at {:?}", self.target);
+
+    match policy {
+      Some(policy) => policy.recover::<T, _>(&hook, panic, original),
+      None => PanicPolicy::Abort.recover::<T, _>(&hook, panic, original),
+    }
+  }
+}
+
+/// Records the thunk a call passes through, and continues at its shim.
+///
+/// A guarded call, made whilst executing the closure, continues at the
+/// trampoline instead.
+unsafe extern "C" fn enter_thunk<T: Function>(
+  stack: usize,
+  context: &ClosureContext<T>,
+) -> *const () {
+  if context.reentrancy_guard.load(Ordering::SeqCst)
+    && reentrancy::is_executing(context.target as usize)
+  {
+    return context.trampoline;
+  }
+
+  if context.variadic {
+    variadic::capture(stack);
+  }
+
+  THUNK.with(|thunk| thunk.set(context as *const ClosureContext<T> as *const ()));
+  context.shim
+}
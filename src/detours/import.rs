@@ -0,0 +1,173 @@
+use crate::arch::memory;
+use crate::elf;
+use crate::error::{Error, Result};
+use crate::reentrancy::{self, ReentrancyGuard};
+use crate::util;
+use crate::{Function, HookableWith};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A type-safe detour of a module's import.
+///
+/// Instead of patching the target's code, the entry of the global offset table
+/// (GOT) used by a module to call an imported function is replaced. Only calls
+/// made by that module are affected, and the original function can be called
+/// directly, without any trampoline. An entry that is yet to be bound (i.e
+/// lazy binding) is resolved beforehand.
+///
+/// The module is referred to by its path or file name (e.g `libfoo.so`), and an
+/// empty name refers to the main executable.
+///
+/// Due to being generated by a macro, the `ImportDetour::call` method is not
+/// exposed in the documentation. It accepts the same arguments as `T`, and
+/// shares its result type.
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::ImportDetour;
+///
+/// type FnGetppid = unsafe extern "C" fn() -> libc::pid_t;
+///
+/// unsafe extern "C" fn getppid_detour() -> libc::pid_t {
+///   1
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let hook = unsafe {
+///   ImportDetour::<FnGetppid>::new("", "getppid", getppid_detour as FnGetppid)?
+/// };
+///
+/// unsafe { hook.enable()? };
+///
+/// assert_eq!(unsafe { libc::getppid() }, 1);
+/// assert_ne!(unsafe { hook.call() }, 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ImportDetour<T: Function> {
+  phantom: PhantomData<T>,
+  slot: *const AtomicUsize,
+  original: *const (),
+  detour: *const (),
+  enabled: AtomicBool,
+}
+
+impl<T: Function> ImportDetour<T> {
+  /// Create a new hook given a module, the name of one of its imports and a
+  /// compatible detour function.
+  pub unsafe fn new<D>(module: &str, import: &str, detour: D) -> Result<Self>
+  where
+    T: HookableWith<D>,
+    D: Function,
+  {
+    let module = elf::module(module)?;
+    let slot = module.import_slot(import).ok_or(Error::SymbolNotFound)? as *const AtomicUsize;
+
+    // A lazily bound entry leads to the module's PLT, until the function is
+    // first called. Calling it through there would bind the entry, replacing
+    // the detour, so the function is resolved instead.
+    let mut original = (*slot).load(Ordering::SeqCst) as *const ();
+    if module.contains(original as usize) {
+      original = module.resolve_import(import).ok_or(Error::SymbolNotFound)?;
+    }
+
+    Ok(ImportDetour {
+      phantom: PhantomData,
+      original,
+      detour: detour.to_ptr(),
+      enabled: AtomicBool::default(),
+      slot,
+    })
+  }
+
+  /// Enables the detour.
+  pub unsafe fn enable(&self) -> Result<()> {
+    self.toggle(true)
+  }
+
+  /// Disables the detour.
+  pub unsafe fn disable(&self) -> Result<()> {
+    self.toggle(false)
+  }
+
+  /// Returns whether the detour is enabled or not.
+  pub fn is_enabled(&self) -> bool {
+    self.enabled.load(Ordering::SeqCst)
+  }
+
+  /// Returns the address of the replaced GOT entry.
+  pub fn slot(&self) -> *const () {
+    self.slot as *const ()
+  }
+
+  /// Marks the current thread as executing the detour, unless it already is.
+  ///
+  /// It's intended to be called within the detour, which may then forward
+  /// any nested calls (i.e when `None` is returned) to the original function.
+  /// Calls through the GOT entry always reach the detour, so it has to
+  /// forward nested calls itself.
+  pub fn enter(&self) -> Option<ReentrancyGuard> {
+    reentrancy::enter(self.slot as usize)
+  }
+
+  /// Returns whether the current thread is executing the detour, i.e whether
+  /// a call to the target would be nested.
+  pub fn is_nested(&self) -> bool {
+    reentrancy::is_executing(self.slot as usize)
+  }
+
+  /// Returns a reference to the original function.
+  pub(crate) fn trampoline(&self) -> &() {
+    unsafe {
+      self
+        .original
+        .as_ref()
+        .expect("original function should not be null")
+    }
+  }
+
+  /// Enables or disables the detour.
+  unsafe fn toggle(&self, enabled: bool) -> Result<()> {
+    // Entries may share a page, whose protection must not be restored whilst
+    // another entry is being written.
+    let _lock = memory::POOL.lock().unwrap();
+
+    if self.enabled.load(Ordering::SeqCst) == enabled {
+      return Ok(());
+    }
+
+    // The entry may reside within a read-only segment (i.e RELRO)
+    let _guard = util::make_writable(self.slot)?;
+
+    let function = if enabled { self.detour } else { self.original };
+    (*self.slot).store(function as usize, Ordering::SeqCst);
+    self.enabled.store(enabled, Ordering::SeqCst);
+    Ok(())
+  }
+}
+
+impl<T: Function> Drop for ImportDetour<T> {
+  /// Disables the detour, if enabled.
+  fn drop(&mut self) {
+    let result = unsafe { self.disable() };
+    debug_assert!(result.is_ok());
+  }
+}
+
+impl<T: Function> fmt::Debug for ImportDetour<T> {
+  /// Output whether the detour is enabled or not.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "ImportDetour {{ enabled: {}, slot: {:?} }}",
+      self.is_enabled(),
+      self.slot()
+    )
+  }
+}
+
+unsafe impl<T: Function> Send for ImportDetour<T> {}
+unsafe impl<T: Function> Sync for ImportDetour<T> {}
@@ -0,0 +1,147 @@
+use crate::arch::{self, memory, Context, Detour};
+use crate::error::{Error, Result};
+use crate::info::DetourInfo;
+use crate::traits::private;
+use crate::{alloc, util};
+use std::{fmt, mem, ptr};
+
+/// The callback type of a mid-function hook.
+type Callback = Box<dyn Fn(&mut Context) + Send + Sync>;
+
+/// A hook placed at an arbitrary instruction.
+///
+/// When the hooked instruction is reached, all general-purpose registers, the
+/// flags and all XMM registers are saved to a [Context](./struct.Context.html),
+/// which is passed to the callback. Once it returns, the (possibly modified)
+/// context is restored, and execution resumes at the hooked instruction.
+///
+/// The address must be at an instruction boundary, and the instructions
+/// replaced by the hook must not be the destination of any branch.
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::MidHook;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static CALLS: AtomicUsize = AtomicUsize::new(0);
+///
+/// #[inline(never)]
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let hook = unsafe {
+///   MidHook::new(add5 as *const (), |_context| {
+///     // The registers can be both inspected and modified
+///     CALLS.fetch_add(1, Ordering::SeqCst);
+///   })?
+/// };
+///
+/// unsafe { hook.enable()? };
+/// assert_eq!(add5(5), 10);
+/// assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MidHook {
+  detour: Detour,
+  #[allow(dead_code)]
+  stub: alloc::ExecutableMemory,
+  #[allow(dead_code)]
+  callback: Box<Callback>,
+}
+
+impl MidHook {
+  /// Constructs a new mid-function hook at an instruction's address.
+  ///
+  /// The hook is disabled by default.
+  pub unsafe fn new<C>(address: *const (), callback: C) -> Result<Self>
+  where
+    C: Fn(&mut Context) + Send + Sync + 'static,
+  {
+    if !util::is_executable_address(address)? {
+      Err(Error::NotExecutable)?;
+    }
+
+    let callback: Box<Callback> = Box::new(Box::new(callback));
+    let emitter = arch::stub_builder(
+      dispatch as *const () as usize,
+      &*callback as *const Callback as usize,
+    );
+
+    let stub = {
+      let mut pool = memory::POOL.lock().unwrap();
+      memory::allocate_pic(&mut pool, &emitter, address)?
+    };
+
+    let detour = Detour::new(address, stub.as_ptr() as *const ())?;
+
+    // A hot patch would overwrite the instructions before the address, which
+    // are part of the same function.
+    let info = detour.info();
+    if info.uses_hot_patch {
+      Err(Error::NoPatchArea {
+        available: info.prolog_size,
+        required: info.target as usize - info.patch_area.start,
+      })?;
+    }
+
+    // The stub resumes execution at the relocated instructions
+    let slot = stub.as_ptr() as usize + stub.len() - mem::size_of::<usize>();
+    ptr::write_unaligned(
+      slot as *mut usize,
+      detour.trampoline() as *const () as usize,
+    );
+
+    Ok(MidHook {
+      detour,
+      stub,
+      callback,
+    })
+  }
+
+  /// Enables the hook.
+  pub unsafe fn enable(&self) -> Result<()> {
+    self.detour.enable()
+  }
+
+  /// Disables the hook.
+  pub unsafe fn disable(&self) -> Result<()> {
+    self.detour.disable()
+  }
+
+  /// Returns whether the hook is enabled or not.
+  pub fn is_enabled(&self) -> bool {
+    self.detour.is_enabled()
+  }
+
+  /// Returns the address of the patched code.
+  pub fn target(&self) -> *const () {
+    self.detour.target()
+  }
+
+  /// Returns a description of how the hook is applied.
+  pub fn info(&self) -> DetourInfo {
+    self.detour.info()
+  }
+}
+
+impl private::Sealed for MidHook {
+  fn base(&self) -> Result<&Detour> {
+    Ok(&self.detour)
+  }
+}
+
+impl fmt::Debug for MidHook {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "MidHook {{ detour: {:?} }}", self.detour)
+  }
+}
+
+/// Invokes a hook's callback with the saved context.
+unsafe extern "C" fn dispatch(context: &mut Context, callback: &Callback) {
+  callback(context);
+}
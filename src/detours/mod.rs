@@ -1,15 +1,25 @@
 use cfg_if::cfg_if;
 
+mod chain;
 mod generic;
+mod mid;
 mod raw;
+mod scoped;
+mod statik;
+mod vtable;
 
+pub(crate) use self::chain::ChainState;
+pub use self::chain::{HookChain, LinkId, Next};
 pub use self::generic::*;
+pub use self::mid::*;
 pub use self::raw::*;
+pub use self::scoped::*;
+pub use self::statik::*;
+pub use self::vtable::*;
 
 cfg_if! {
-    if #[cfg(feature = "nightly")] {
-        mod statik;
-        pub use self::statik::*;
-    } else {
+    if #[cfg(target_os = "linux")] {
+        mod import;
+        pub use self::import::*;
     }
 }
@@ -1,5 +1,9 @@
 use crate::arch::Detour;
+use crate::detours::EnableGuard;
 use crate::error::Result;
+use crate::info::DetourInfo;
+use crate::reentrancy::{self, ReentrancyGuard};
+use crate::traits::private;
 
 /// A raw detour.
 ///
@@ -40,7 +44,6 @@ use crate::error::Result;
 #[derive(Debug)]
 pub struct RawDetour(Detour);
 
-// TODO: stop all threads in target during patch?
 impl RawDetour {
   /// Constructs a new inline detour patcher.
   ///
@@ -53,6 +56,17 @@ impl RawDetour {
     Detour::new(target, detour).map(RawDetour)
   }
 
+  /// Constructs a new inline detour patcher for a module's symbol.
+  ///
+  /// The module is referred to by its path or file name, and an empty name
+  /// refers to the main executable. Symbols that are not exported are looked
+  /// up in the module's symbol tables, and may be referred to by their
+  /// demangled name (e.g `crate::module::function`).
+  #[cfg(target_os = "linux")]
+  pub unsafe fn from_symbol(module: &str, symbol: &str, detour: *const ()) -> Result<Self> {
+    Self::new(crate::elf::symbol(module, symbol)?, detour)
+  }
+
   /// Enables the detour.
   pub unsafe fn enable(&self) -> Result<()> {
     self.0.enable()
@@ -63,13 +77,93 @@ impl RawDetour {
     self.0.disable()
   }
 
+  /// Enables the detour until the returned guard is dropped.
+  ///
+  /// Scopes may overlap, in which case the detour remains enabled until the
+  /// last of them ends.
+  pub unsafe fn enable_scoped(&self) -> Result<EnableGuard<'_>> {
+    EnableGuard::new(&self.0)
+  }
+
+  /// Invokes a closure whilst the detour is enabled.
+  ///
+  /// The detour is disabled afterwards, even if the closure panics.
+  pub unsafe fn with_enabled<F, R>(&self, closure: F) -> Result<R>
+  where
+    F: FnOnce() -> R,
+  {
+    let _guard = self.enable_scoped()?;
+    Ok(closure())
+  }
+
   /// Returns whether the detour is enabled or not.
   pub fn is_enabled(&self) -> bool {
     self.0.is_enabled()
   }
 
+  /// Sets whether the detour only applies to threads which have opted in,
+  /// using `enable_for_current_thread` (disabled by default).
+  ///
+  /// Any other thread calls the original function, as if it wasn't hooked.
+  pub unsafe fn set_per_thread(&self, per_thread: bool) -> Result<()> {
+    self.0.set_per_thread(per_thread)
+  }
+
+  /// Opts in the current thread, when the detour is applied per thread.
+  pub fn enable_for_current_thread(&self) -> Result<()> {
+    self.0.enable_for_current_thread()
+  }
+
+  /// Opts out the current thread, when the detour is applied per thread.
+  pub fn disable_for_current_thread(&self) {
+    self.0.disable_for_current_thread()
+  }
+
+  /// Returns whether the current thread has opted in.
+  pub fn is_enabled_for_current_thread(&self) -> bool {
+    self.0.is_enabled_for_current_thread()
+  }
+
+  /// Returns the address of the patched code.
+  pub fn target(&self) -> *const () {
+    self.0.target()
+  }
+
+  /// Returns a description of how the detour is applied.
+  pub fn info(&self) -> DetourInfo {
+    self.0.info()
+  }
+
+  /// Assigns a label to the detour, identifying it within the
+  /// [registry](./registry/index.html).
+  pub fn set_label<S: Into<String>>(&self, label: S) {
+    self.0.set_label(Some(label.into()));
+  }
+
+  /// Marks the current thread as executing the detour, unless it already is.
+  ///
+  /// It's intended to be called within the detour, which may then forward
+  /// any nested calls (i.e when `None` is returned) to the original function.
+  /// The detour is invoked directly, so nested calls are never forwarded
+  /// automatically.
+  pub fn enter(&self) -> Option<ReentrancyGuard> {
+    reentrancy::enter(self.target() as usize)
+  }
+
+  /// Returns whether the current thread is executing the detour, i.e whether
+  /// a call to the target would be nested.
+  pub fn is_nested(&self) -> bool {
+    reentrancy::is_executing(self.target() as usize)
+  }
+
   /// Returns a reference to the generated trampoline.
   pub fn trampoline(&self) -> &() {
     self.0.trampoline()
   }
 }
+
+impl private::Sealed for RawDetour {
+  fn base(&self) -> Result<&Detour> {
+    Ok(&self.0)
+  }
+}
@@ -0,0 +1,47 @@
+use crate::alloc;
+use crate::arch::Detour;
+use crate::error::Result;
+use std::fmt;
+use std::sync::MutexGuard;
+
+/// A guard keeping a detour enabled until it's dropped.
+///
+/// It's obtained using the `enable_scoped` method of a detour. The detour is
+/// also disabled if the thread unwinds. Several scopes may enable the same
+/// detour at once, in which case it remains enabled until the last guard is
+/// dropped, or for as long as it's enabled using `enable`.
+#[must_use = "the detour is disabled as soon as the guard is dropped"]
+pub struct EnableGuard<'a> {
+  detour: &'a Detour,
+}
+
+impl<'a> EnableGuard<'a> {
+  /// Enables a detour within a new scope.
+  pub(crate) unsafe fn new(detour: &'a Detour) -> Result<Self> {
+    detour.enter_scope()?;
+    Ok(EnableGuard { detour })
+  }
+
+  /// Enables a detour within a new scope, with the memory lock already held.
+  pub(crate) unsafe fn new_locked(
+    guard: MutexGuard<'_, alloc::ThreadAllocator>,
+    detour: &'a Detour,
+  ) -> Result<Self> {
+    detour.enter_scope_locked(guard)?;
+    Ok(EnableGuard { detour })
+  }
+}
+
+impl<'a> Drop for EnableGuard<'a> {
+  /// Disables the detour, unless it's enabled elsewhere.
+  fn drop(&mut self) {
+    let result = unsafe { self.detour.exit_scope() };
+    debug_assert!(result.is_ok());
+  }
+}
+
+impl<'a> fmt::Debug for EnableGuard<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "EnableGuard {{ detour: {:?} }}", self.detour)
+  }
+}
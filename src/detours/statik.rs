@@ -1,20 +1,59 @@
+use crate::arch::{memory, Detour};
+use crate::detours::EnableGuard;
 use crate::error::{Error, Result};
+use crate::info::DetourInfo;
+use crate::panic::{Panic, PanicPolicy};
+use crate::reentrancy::{self, ReentrancyGuard};
+use crate::thread::Freeze;
+use crate::traits::private;
 use crate::{Function, GenericDetour};
-use std::sync::atomic::{AtomicPtr, Ordering};
-use std::{mem, ptr};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::{mem, ptr, thread};
 
 /// A type-safe static detour.
 ///
-/// Due to being generated by a macro, the `StaticDetour::call` method is not
-/// exposed in the documentation.  
+/// Due to being generated by a macro, the `StaticDetour::initialize`,
+/// `StaticDetour::set_detour` and `StaticDetour::call` methods are not exposed
+/// in the documentation.
 ///
 /// ```c
+/// /// Create a new hook given a target function and a compatible detour
+/// /// closure.
+/// ///
+/// /// This method can only be called once per static instance, unless it has
+/// /// been reset. Multiple calls will error with `AlreadyExisting`. It returns
+/// /// `&self` to allow chaining initialization and activation.
+/// unsafe fn initialize<D>(&self, target: T, closure: D) -> Result<&Self>
+///   where D: Fn(T::Arguments) -> T::Output + Send + Sync + 'static
+///
+/// /// Changes the detour, regardless of whether the hook is enabled or not.
+/// fn set_detour<C>(&self, closure: C)
+///   where C: Fn(T::Arguments) -> T::Output + Send + Sync + 'static
+///
 /// /// Calls the original function regardless of whether it's hooked or not.
 /// ///
 /// /// Panics if called when the static detour has not yet been initialized.
+/// /// Once it has been reset, the target is called directly.
 /// fn call(&self, T::Arguments) -> T::Output
 /// ```
 ///
+/// The closure may be invoked by several threads at once, so it must be
+/// `Sync`:
+///
+/// ```compile_fail
+/// # use detour::static_detour;
+/// # use std::cell::Cell;
+/// # static_detour! {
+/// #   static Test: fn(i32) -> i32;
+/// # }
+/// # fn add5(val: i32) -> i32 {
+/// #   val + 5
+/// # }
+/// let calls = Cell::new(0);
+/// unsafe { Test.initialize(add5, move |val| val + calls.replace(calls.get() + 1)) };
+/// ```
+///
 /// To define a static detour, use the
 /// [static_detour](./macro.static_detour.html) macro.
 ///
@@ -62,49 +101,39 @@ use std::{mem, ptr};
 /// }
 /// ```
 pub struct StaticDetour<T: Function> {
-  closure: AtomicPtr<Box<dyn Fn<T::Arguments, Output = T::Output>>>,
+  closure: RwLock<Option<Arc<T::Closure>>>,
   detour: AtomicPtr<GenericDetour<T>>,
+  target: AtomicPtr<()>,
+  calls: AtomicUsize,
+  reentrancy_guard: AtomicBool,
+  panic_policy: RwLock<Option<Arc<PanicPolicy<T::Output>>>>,
+  name: &'static str,
   ffi: T,
 }
 
 impl<T: Function> StaticDetour<T> {
   /// Create a new static detour.
   #[doc(hidden)]
-  pub const fn __new(ffi: T) -> Self {
+  pub const fn __new(ffi: T, name: &'static str) -> Self {
     StaticDetour {
-      closure: AtomicPtr::new(ptr::null_mut()),
+      closure: RwLock::new(None),
       detour: AtomicPtr::new(ptr::null_mut()),
+      target: AtomicPtr::new(ptr::null_mut()),
+      calls: AtomicUsize::new(0),
+      reentrancy_guard: AtomicBool::new(false),
+      panic_policy: RwLock::new(None),
+      name,
       ffi,
     }
   }
 
-  /// Create a new hook given a target function and a compatible detour
-  /// closure.
-  ///
-  /// This method can only be called once per static instance. Multiple calls
-  /// will error with `AlreadyExisting`.
-  ///
-  /// It returns `&self` to allow chaining initialization and activation:
-  ///
-  /// ```rust
-  /// # use detour::{Result, static_detour};
-  /// # static_detour! {
-  /// #   static Test: fn(i32) -> i32;
-  /// # }
-  /// #
-  /// # fn add5(val: i32) -> i32 {
-  /// #   val + 5
-  /// # }
-  /// #
-  /// # fn main() -> Result<()> {
-  /// unsafe { Test.initialize(add5, |x| x - 5)?.enable()? };
-  /// # Ok(())
-  /// # }
-  /// ```
-  pub unsafe fn initialize<D>(&self, target: T, closure: D) -> Result<&Self>
-  where
-    D: Fn<T::Arguments, Output = T::Output> + Send + 'static,
-  {
+  /// Creates a new hook, with a boxed detour closure.
+  pub(crate) unsafe fn initialize_boxed(
+    &self,
+    target: T,
+    closure: Box<T::Closure>,
+  ) -> Result<&Self> {
+    let address = target.to_ptr();
     let mut detour = Box::new(GenericDetour::new(target, self.ffi)?);
     if self
       .detour
@@ -119,77 +148,283 @@ impl<T: Function> StaticDetour<T> {
       Err(Error::AlreadyInitialized)?;
     }
 
-    self.set_detour(closure);
+    // The detour is identified by its path within the registry
+    detour.set_label(self.name);
+    self.target.store(address as *mut (), Ordering::SeqCst);
+
+    self.set_boxed_detour(closure);
     mem::forget(detour);
     Ok(self)
   }
 
   /// Enables the detour.
   pub unsafe fn enable(&self) -> Result<()> {
-    self
-      .detour
-      .load(Ordering::SeqCst)
-      .as_ref()
-      .ok_or(Error::NotInitialized)?
-      .enable()
+    self.toggle(true)
   }
 
   /// Disables the detour.
   pub unsafe fn disable(&self) -> Result<()> {
+    self.toggle(false)
+  }
+
+  /// Enables the detour until the returned guard is dropped.
+  ///
+  /// Scopes may overlap, in which case the detour remains enabled until the
+  /// last of them ends.
+  pub unsafe fn enable_scoped(&self) -> Result<EnableGuard<'_>> {
+    // The lock keeps the detour from being reset meanwhile
+    let pool = memory::POOL.lock().unwrap();
+    EnableGuard::new_locked(pool, private::Sealed::base(self)?)
+  }
+
+  /// Invokes a closure whilst the detour is enabled.
+  ///
+  /// The detour is disabled afterwards, even if the closure panics.
+  pub unsafe fn with_enabled<F, R>(&self, closure: F) -> Result<R>
+  where
+    F: FnOnce() -> R,
+  {
+    let _guard = self.enable_scoped()?;
+    Ok(closure())
+  }
+
+  /// Returns whether the detour is enabled or not.
+  pub fn is_enabled(&self) -> bool {
+    unsafe { self.detour.load(Ordering::SeqCst).as_ref() }
+      .map(|detour| detour.is_enabled())
+      .unwrap_or(false)
+  }
+
+  /// Sets whether the detour only applies to threads which have opted in,
+  /// using `enable_for_current_thread` (disabled by default).
+  ///
+  /// Any other thread calls the original function, as if it wasn't hooked.
+  pub unsafe fn set_per_thread(&self, per_thread: bool) -> Result<()> {
     self
       .detour
       .load(Ordering::SeqCst)
       .as_ref()
       .ok_or(Error::NotInitialized)?
-      .disable()
+      .set_per_thread(per_thread)
   }
 
-  /// Returns whether the detour is enabled or not.
-  pub fn is_enabled(&self) -> bool {
+  /// Opts in the current thread, when the detour is applied per thread.
+  pub fn enable_for_current_thread(&self) -> Result<()> {
     unsafe { self.detour.load(Ordering::SeqCst).as_ref() }
-      .map(|detour| detour.is_enabled())
-      .unwrap_or(false)
+      .ok_or(Error::NotInitialized)?
+      .enable_for_current_thread()
   }
 
-  /// Changes the detour, regardless of whether the hook is enabled or not.
-  pub fn set_detour<C>(&self, closure: C)
-  where
-    C: Fn<T::Arguments, Output = T::Output> + Send + 'static,
-  {
-    let previous = self
-      .closure
-      .swap(Box::into_raw(Box::new(Box::new(closure))), Ordering::SeqCst);
-    if !previous.is_null() {
-      mem::drop(unsafe { Box::from_raw(previous) });
+  /// Opts out the current thread, when the detour is applied per thread.
+  pub fn disable_for_current_thread(&self) {
+    if let Some(detour) = unsafe { self.detour.load(Ordering::SeqCst).as_ref() } {
+      detour.disable_for_current_thread();
     }
   }
 
-  /// Returns a reference to the generated trampoline.
-  pub(crate) fn trampoline(&self) -> Result<&()> {
+  /// Returns whether the current thread has opted in.
+  pub fn is_enabled_for_current_thread(&self) -> bool {
+    unsafe { self.detour.load(Ordering::SeqCst).as_ref() }
+      .map(|detour| detour.is_enabled_for_current_thread())
+      .unwrap_or(false)
+  }
+
+  /// Returns a description of how the detour is applied.
+  pub fn info(&self) -> Result<DetourInfo> {
     Ok(
       unsafe { self.detour.load(Ordering::SeqCst).as_ref() }
         .ok_or(Error::NotInitialized)?
-        .trampoline(),
+        .info(),
     )
   }
 
-  /// Returns a transient reference to the active detour.
-  #[doc(hidden)]
-  pub fn __detour(&self) -> &dyn Fn<T::Arguments, Output = T::Output> {
-    // TODO: This is not 100% thread-safe in case the thread is stopped
-    unsafe { self.closure.load(Ordering::SeqCst).as_ref() }
-      .ok_or(Error::NotInitialized)
-      .expect("retrieving detour closure")
+  /// Sets whether calls to the target, made by a thread already executing the
+  /// detour, are forwarded to the original function (disabled by default).
+  ///
+  /// This prevents detours of e.g `malloc` from recursing infinitely, when
+  /// any code they invoke calls the target.
+  pub fn set_reentrancy_guard(&self, enabled: bool) {
+    self.reentrancy_guard.store(enabled, Ordering::SeqCst);
+  }
+
+  /// Returns whether the current thread is executing the detour, i.e whether
+  /// a call to the target would be nested.
+  pub fn is_nested(&self) -> bool {
+    reentrancy::is_executing(self as *const Self as usize)
+  }
+
+  /// Sets how a panic within the detour's closure is handled (aborting by
+  /// default).
+  pub fn set_panic_policy(&self, policy: PanicPolicy<T::Output>) {
+    *self.panic_policy.write().unwrap() = Some(Arc::new(policy));
+  }
+
+  /// Uninitializes the detour, allowing it to be initialized once again.
+  ///
+  /// The detour is disabled, and once no thread is executing the detour, its
+  /// relay or its trampoline, its resources are released. It fails with
+  /// `InUse` whilst any guard returned by `enable_scoped` is alive. Calls
+  /// made meanwhile are forwarded to the target.
+  ///
+  /// This must not be called from within the detour, since it would never
+  /// return.
+  ///
+  /// ```rust
+  /// # use detour::{Result, static_detour};
+  /// # static_detour! {
+  /// #   static Test: fn(i32) -> i32;
+  /// # }
+  /// #
+  /// # fn add5(val: i32) -> i32 {
+  /// #   val + 5
+  /// # }
+  /// #
+  /// # fn main() -> Result<()> {
+  /// unsafe {
+  ///   Test.initialize(add5, |x| x - 5)?.enable()?;
+  ///   Test.reset()?;
+  ///
+  ///   assert_eq!(add5(1), 6);
+  ///   Test.initialize(add5, |x| x * 5)?.enable()?;
+  ///   assert_eq!(add5(1), 5);
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub unsafe fn reset(&self) -> Result<()> {
+    // The lock keeps the detour from being toggled or scoped meanwhile, so it's
+    // unreachable by the time anyone may enable it again.
+    let pool = memory::POOL.lock().unwrap();
+    let base = private::Sealed::base(self)?;
+    let detour = self.detour.load(Ordering::SeqCst);
+
+    let mut closure = None;
+    base.retire(pool, || {
+      self.detour.store(ptr::null_mut(), Ordering::SeqCst);
+      closure = self.closure.write().unwrap().take();
+    })?;
+    mem::drop(closure);
+
+    // Threads which have entered the relay or the trampoline, but not yet the
+    // detour, are not accounted for by its calls. Therefore the detour is only
+    // released whilst no suspended thread is executing any of its code. If
+    // threads can't be suspended, it's leaked instead.
+    loop {
+      while self.calls.load(Ordering::SeqCst) > 0 {
+        thread::yield_now();
+      }
+
+      let freeze = Freeze::new()?;
+      if self.calls.load(Ordering::SeqCst) == 0 && !freeze.any(|address| base.contains(address)) {
+        break;
+      }
+
+      mem::drop(freeze);
+      thread::yield_now();
+    }
+
+    mem::drop(Box::from_raw(detour));
+    Ok(())
+  }
+
+  /// Changes the detour to a boxed closure.
+  ///
+  /// Calls in progress keep a reference to the previous closure, which is
+  /// released once the last of them returns.
+  pub(crate) fn set_boxed_detour(&self, closure: Box<T::Closure>) {
+    let previous = self.closure.write().unwrap().replace(Arc::from(closure));
+    mem::drop(previous);
+  }
+
+  /// Returns a reference to the generated trampoline.
+  ///
+  /// Once the detour has been reset, it's disabled, so the target itself is
+  /// returned instead.
+  pub(crate) fn trampoline(&self) -> Result<&()> {
+    match unsafe { self.detour.load(Ordering::SeqCst).as_ref() } {
+      Some(detour) => Ok(detour.trampoline()),
+      None => unsafe { (self.target.load(Ordering::SeqCst) as *const ()).as_ref() }
+        .ok_or(Error::NotInitialized),
+    }
+  }
+
+  /// Enables or disables the detour.
+  unsafe fn toggle(&self, enabled: bool) -> Result<()> {
+    // The lock keeps the detour from being reset meanwhile
+    let pool = memory::POOL.lock().unwrap();
+    Detour::toggle_all_locked(pool, &[(private::Sealed::base(self)?, enabled)])
+  }
+
+  /// Handles a panic caught within the detour's closure.
+  pub(crate) fn recover<F>(&self, panic: Panic<T::Arguments>, original: F) -> T::Output
+  where
+    F: FnOnce(T::Arguments) -> T::Output,
+  {
+    let policy = self.panic_policy.read().unwrap().clone();
+    let hook = format!("`{}`", self.name);
+
+    match policy {
+      Some(policy) => policy.recover::<T, _>(&hook, panic, original),
+      None => PanicPolicy::Abort.recover::<T, _>(&hook, panic, original),
+    }
+  }
+
+  /// Accounts for a thread executing the detour or the trampoline.
+  pub(crate) fn track_call(&self) -> ActiveCall<'_> {
+    self.calls.fetch_add(1, Ordering::SeqCst);
+    ActiveCall(&self.calls)
+  }
+
+  /// Enters the active detour, unless the call is nested and should be
+  /// forwarded to the original function.
+  pub(crate) fn enter_detour(&self) -> Option<DetourCall<'_, T>> {
+    let call = self.track_call();
+
+    let key = self as *const Self as usize;
+    let guard = if self.reentrancy_guard.load(Ordering::SeqCst) {
+      reentrancy::enter(key)?
+    } else {
+      reentrancy::enter_nested(key)
+    };
+
+    // The closure is kept alive until the call returns, even if it's replaced.
+    // Once the detour has been reset, the call is forwarded instead.
+    let closure = self.closure.read().unwrap().clone()?;
+
+    Some(DetourCall {
+      closure,
+      _call: call,
+      _guard: guard,
+    })
   }
 }
 
-impl<T: Function> Drop for StaticDetour<T> {
+/// A call to a static detour in progress.
+pub(crate) struct DetourCall<'a, T: Function> {
+  pub closure: Arc<T::Closure>,
+  _call: ActiveCall<'a>,
+  _guard: ReentrancyGuard,
+}
+
+/// A call to a static detour, or its trampoline, in progress.
+pub(crate) struct ActiveCall<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for ActiveCall<'a> {
   fn drop(&mut self) {
-    let previous = self.closure.swap(ptr::null_mut(), Ordering::Relaxed);
-    if !previous.is_null() {
-      mem::drop(unsafe { Box::from_raw(previous) });
-    }
+    self.0.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl<T: Function> private::Sealed for StaticDetour<T> {
+  fn base(&self) -> Result<&Detour> {
+    unsafe { self.detour.load(Ordering::SeqCst).as_ref() }
+      .ok_or(Error::NotInitialized)?
+      .base()
+  }
+}
 
+impl<T: Function> Drop for StaticDetour<T> {
+  fn drop(&mut self) {
     let previous = self.detour.swap(ptr::null_mut(), Ordering::Relaxed);
     if !previous.is_null() {
       unsafe { Box::from_raw(previous) };
@@ -0,0 +1,274 @@
+use crate::arch::memory;
+use crate::error::{Error, Result};
+use crate::reentrancy::{self, ReentrancyGuard};
+use crate::util;
+use crate::{Function, HookableWith};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{fmt, slice};
+
+/// The amount of words preceding a vtable's methods (i.e RTTI), which are
+/// copied to a shadow vtable.
+#[cfg(windows)]
+const PREFIX: usize = 1;
+#[cfg(not(windows))]
+const PREFIX: usize = 2;
+
+/// A type-safe detour of a virtual method.
+///
+/// Instead of patching the method's code, a slot of a virtual method table
+/// (vtable) is replaced. Either the vtable is modified in place, affecting
+/// every object which uses it, or a single object is given its own copy of the
+/// vtable (a shadow), leaving any other objects unaffected.
+///
+/// The method is referred to by its index within the vtable, and its type
+/// includes the object pointer (i.e `this`) as the first argument.
+///
+/// Due to being generated by a macro, the `VTableDetour::call` method is not
+/// exposed in the documentation. It accepts the same arguments as `T`, and
+/// shares its result type.
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::VTableDetour;
+/// use std::ptr;
+///
+/// #[repr(C)]
+/// struct Object {
+///   vtable: *const [FnValue; 1],
+///   value: i32,
+/// }
+///
+/// type FnValue = extern "C" fn(*const Object) -> i32;
+///
+/// static VTABLE: [FnValue; 1] = [value];
+///
+/// extern "C" fn value(this: *const Object) -> i32 {
+///   unsafe { (*this).value }
+/// }
+///
+/// extern "C" fn value_detour(this: *const Object) -> i32 {
+///   unsafe { (*this).value * 2 }
+/// }
+///
+/// fn call_value(object: &Object) -> i32 {
+///   let method = unsafe { ptr::read_volatile(&(*object.vtable)[0]) };
+///   method(object)
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let object = Object { vtable: &VTABLE, value: 5 };
+/// let hook = unsafe {
+///   VTableDetour::<FnValue>::new(VTABLE.as_ptr() as *const *const (), 0, value_detour)?
+/// };
+///
+/// unsafe { hook.enable()? };
+///
+/// assert_eq!(call_value(&object), 10);
+/// assert_eq!(hook.call(&object), 5);
+/// # Ok(())
+/// # }
+/// ```
+pub struct VTableDetour<T: Function> {
+  phantom: PhantomData<T>,
+  slot: *const AtomicUsize,
+  original: *const (),
+  detour: *const (),
+  shadow: Option<Shadow>,
+  enabled: AtomicBool,
+}
+
+/// An object's own copy of its vtable.
+struct Shadow {
+  /// The object's vtable pointer.
+  vptr: *const AtomicUsize,
+  /// The address of the object's original vtable.
+  vtable: usize,
+  /// The vtable's prefix and methods.
+  words: Box<[usize]>,
+}
+
+impl<T: Function> VTableDetour<T> {
+  /// Create a new hook given a vtable, the index of a method within it and a
+  /// compatible detour function.
+  ///
+  /// The vtable is modified in place, so every object which uses it is
+  /// affected.
+  pub unsafe fn new<D>(vtable: *const *const (), index: usize, detour: D) -> Result<Self>
+  where
+    T: HookableWith<D>,
+    D: Function,
+  {
+    let slot = vtable.add(index) as *const AtomicUsize;
+    Self::with_slot(slot, detour.to_ptr(), None)
+  }
+
+  /// Create a new hook given an object, the index of a method within its
+  /// vtable, the amount of methods in the vtable and a compatible detour
+  /// function.
+  ///
+  /// Once enabled, the object uses a copy of its vtable, so no other objects
+  /// are affected. The object's vtable pointer is expected to be its first
+  /// field, and the object must outlive the hook.
+  pub unsafe fn for_object<D>(object: *mut (), index: usize, len: usize, detour: D) -> Result<Self>
+  where
+    T: HookableWith<D>,
+    D: Function,
+  {
+    assert!(index < len, "vtable index out of bounds");
+
+    let vptr = object as *const AtomicUsize;
+    let vtable = (*vptr).load(Ordering::SeqCst);
+
+    // The prefix is included, so the object's type can still be identified
+    let start = (vtable as *const usize).sub(PREFIX);
+    let words = slice::from_raw_parts(start, PREFIX + len).into();
+    let mut shadow = Shadow {
+      vptr,
+      vtable,
+      words,
+    };
+
+    let slot = &mut shadow.words[PREFIX + index] as *mut usize as *const AtomicUsize;
+    Self::with_slot(slot, detour.to_ptr(), Some(shadow))
+  }
+
+  /// Enables the detour.
+  pub unsafe fn enable(&self) -> Result<()> {
+    self.toggle(true)
+  }
+
+  /// Disables the detour.
+  pub unsafe fn disable(&self) -> Result<()> {
+    self.toggle(false)
+  }
+
+  /// Returns whether the detour is enabled or not.
+  pub fn is_enabled(&self) -> bool {
+    self.enabled.load(Ordering::SeqCst)
+  }
+
+  /// Returns the address of the replaced vtable slot.
+  pub fn slot(&self) -> *const () {
+    self.slot as *const ()
+  }
+
+  /// Returns whether the hook applies to a single object.
+  pub fn is_shadow(&self) -> bool {
+    self.shadow.is_some()
+  }
+
+  /// Marks the current thread as executing the detour, unless it already is.
+  ///
+  /// It's intended to be called within the detour, which may then forward
+  /// any nested calls (i.e when `None` is returned) to the original function.
+  /// Calls through the slot always reach the detour, so it has to forward
+  /// nested calls itself.
+  pub fn enter(&self) -> Option<ReentrancyGuard> {
+    reentrancy::enter(self.slot as usize)
+  }
+
+  /// Returns whether the current thread is executing the detour, i.e whether
+  /// a call to the method would be nested.
+  pub fn is_nested(&self) -> bool {
+    reentrancy::is_executing(self.slot as usize)
+  }
+
+  /// Returns a reference to the original method.
+  pub(crate) fn trampoline(&self) -> &() {
+    unsafe {
+      self
+        .original
+        .as_ref()
+        .expect("original method should not be null")
+    }
+  }
+
+  /// Creates a hook of a vtable slot.
+  unsafe fn with_slot(
+    slot: *const AtomicUsize,
+    detour: *const (),
+    shadow: Option<Shadow>,
+  ) -> Result<Self> {
+    let original = (*slot).load(Ordering::SeqCst) as *const ();
+    if original == detour {
+      Err(Error::SameAddress)?;
+    }
+
+    if !util::is_executable_address(original)? || !util::is_executable_address(detour)? {
+      Err(Error::NotExecutable)?;
+    }
+
+    // The slot of a shadow vtable always refers to the detour
+    if shadow.is_some() {
+      (*slot).store(detour as usize, Ordering::SeqCst);
+    }
+
+    Ok(VTableDetour {
+      phantom: PhantomData,
+      enabled: AtomicBool::default(),
+      slot,
+      original,
+      detour,
+      shadow,
+    })
+  }
+
+  /// Enables or disables the detour.
+  unsafe fn toggle(&self, enabled: bool) -> Result<()> {
+    // The slots of a vtable share a page, whose protection must not be
+    // restored whilst another slot is being written.
+    let _lock = memory::POOL.lock().unwrap();
+
+    if self.enabled.load(Ordering::SeqCst) == enabled {
+      return Ok(());
+    }
+
+    match &self.shadow {
+      Some(shadow) => {
+        let vtable = if enabled {
+          shadow.words.as_ptr().add(PREFIX) as usize
+        } else {
+          shadow.vtable
+        };
+        (*shadow.vptr).store(vtable, Ordering::SeqCst);
+      },
+      None => {
+        // Vtables usually reside within a read-only segment
+        let _guard = util::make_writable(self.slot)?;
+
+        let function = if enabled { self.detour } else { self.original };
+        (*self.slot).store(function as usize, Ordering::SeqCst);
+      },
+    }
+
+    self.enabled.store(enabled, Ordering::SeqCst);
+    Ok(())
+  }
+}
+
+impl<T: Function> Drop for VTableDetour<T> {
+  /// Disables the detour, if enabled.
+  fn drop(&mut self) {
+    let result = unsafe { self.disable() };
+    debug_assert!(result.is_ok());
+  }
+}
+
+impl<T: Function> fmt::Debug for VTableDetour<T> {
+  /// Output whether the detour is enabled or not.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "VTableDetour {{ enabled: {}, shadow: {}, slot: {:?} }}",
+      self.is_enabled(),
+      self.is_shadow(),
+      self.slot()
+    )
+  }
+}
+
+unsafe impl<T: Function> Send for VTableDetour<T> {}
+unsafe impl<T: Function> Sync for VTableDetour<T> {}
@@ -0,0 +1,474 @@
+//! Inspection of the ELF modules loaded into the process.
+//!
+//! Modules are enumerated using `dl_iterate_phdr`, and their dynamic sections
+//! are parsed to locate the relocation slots of imported symbols. Symbols that
+//! are not exported are located using the symbol tables of the module's file.
+use crate::error::{Error, Result};
+use std::ffi::{CStr, CString};
+use std::ops::Range;
+use std::os::raw::{c_int, c_void};
+use std::{fs, mem, ptr, slice};
+
+cfg_if::cfg_if! {
+  if #[cfg(target_pointer_width = "64")] {
+    type Phdr = libc::Elf64_Phdr;
+
+    /// The ELF file header.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct FileHeader {
+      ident: [u8; 16],
+      kind: u16,
+      machine: u16,
+      version: u32,
+      entry: u64,
+      program_header_offset: u64,
+      section_header_offset: u64,
+      flags: u32,
+      header_size: u16,
+      program_header_size: u16,
+      program_header_count: u16,
+      section_header_size: u16,
+      section_header_count: u16,
+      section_names_index: u16,
+    }
+
+    /// A section header.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct SectionHeader {
+      name: u32,
+      kind: u32,
+      flags: u64,
+      address: u64,
+      offset: u64,
+      size: u64,
+      link: u32,
+      info: u32,
+      alignment: u64,
+      entry_size: u64,
+    }
+
+    /// A symbol table entry.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct Symbol {
+      name: u32,
+      info: u8,
+      other: u8,
+      section: u16,
+      value: u64,
+      size: u64,
+    }
+
+    /// Returns the symbol index of relocation information.
+    fn relocation_symbol(info: usize) -> usize {
+      info >> 32
+    }
+
+    /// Returns the relocation type of relocation information.
+    fn relocation_type(info: usize) -> usize {
+      info & 0xFFFF_FFFF
+    }
+  } else {
+    type Phdr = libc::Elf32_Phdr;
+
+    /// The ELF file header.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct FileHeader {
+      ident: [u8; 16],
+      kind: u16,
+      machine: u16,
+      version: u32,
+      entry: u32,
+      program_header_offset: u32,
+      section_header_offset: u32,
+      flags: u32,
+      header_size: u16,
+      program_header_size: u16,
+      program_header_count: u16,
+      section_header_size: u16,
+      section_header_count: u16,
+      section_names_index: u16,
+    }
+
+    /// A section header.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct SectionHeader {
+      name: u32,
+      kind: u32,
+      flags: u32,
+      address: u32,
+      offset: u32,
+      size: u32,
+      link: u32,
+      info: u32,
+      alignment: u32,
+      entry_size: u32,
+    }
+
+    /// A symbol table entry.
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct Symbol {
+      name: u32,
+      value: u32,
+      size: u32,
+      info: u8,
+      other: u8,
+      section: u16,
+    }
+
+    /// Returns the symbol index of relocation information.
+    fn relocation_symbol(info: usize) -> usize {
+      info >> 8
+    }
+
+    /// Returns the relocation type of relocation information.
+    fn relocation_type(info: usize) -> usize {
+      info & 0xFF
+    }
+  }
+}
+
+/// An entry of the dynamic section.
+#[repr(C)]
+struct Dynamic {
+  tag: isize,
+  value: usize,
+}
+
+/// A relocation entry, sharing its layout with the prefix of those with an
+/// explicit addend.
+#[repr(C)]
+struct Rel {
+  offset: usize,
+  info: usize,
+}
+
+/// The size of a relocation entry with an explicit addend.
+const RELA_SIZE: usize = mem::size_of::<Rel>() + mem::size_of::<isize>();
+
+// Dynamic section tags.
+const DT_NULL: isize = 0;
+const DT_PLTRELSZ: isize = 2;
+const DT_STRTAB: isize = 5;
+const DT_SYMTAB: isize = 6;
+const DT_RELA: isize = 7;
+const DT_RELASZ: isize = 8;
+const DT_REL: isize = 17;
+const DT_RELSZ: isize = 18;
+const DT_PLTREL: isize = 20;
+const DT_JMPREL: isize = 23;
+
+// Relocation types (shared by `R_386_*` & `R_X86_64_*`).
+const R_GLOB_DAT: usize = 6;
+const R_JUMP_SLOT: usize = 7;
+
+// Section types.
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+
+/// The section index of undefined symbols.
+const SHN_UNDEF: u16 = 0;
+
+/// A module loaded into the process.
+pub struct Module {
+  /// The path of the module, empty for the main executable.
+  pub path: String,
+  /// The difference between the module's virtual and load addresses.
+  pub base: usize,
+  /// The module's dynamic section.
+  dynamic: Option<*const Dynamic>,
+  /// The address ranges of the module's loaded segments.
+  segments: Vec<Range<usize>>,
+}
+
+impl Module {
+  /// Returns whether the module is referred to by a name or not.
+  ///
+  /// Either the full path or the file name of a module can be used, and an
+  /// empty name refers to the main executable.
+  pub fn is_named(&self, name: &str) -> bool {
+    self.path == name || self.path.rsplit('/').next() == Some(name)
+  }
+
+  /// Returns the address ranges of the module's loaded segments.
+  pub fn segments(&self) -> &[Range<usize>] {
+    &self.segments
+  }
+
+  /// Returns whether an address is within one of the module's segments.
+  pub fn contains(&self, address: usize) -> bool {
+    self
+      .segments
+      .iter()
+      .any(|segment| segment.contains(&address))
+  }
+
+  /// Returns the address of a symbol defined by the module.
+  ///
+  /// Exported symbols are resolved by the dynamic linker, whilst any other
+  /// symbols are looked up in the symbol tables of the module's file. Besides
+  /// their raw name, symbols can be referred to by their demangled name.
+  pub unsafe fn symbol(&self, name: &str) -> Option<*const ()> {
+    self.dynamic_symbol(name).or_else(|| self.file_symbol(name))
+  }
+
+  /// Resolves an imported symbol the way the dynamic linker binds it, i.e
+  /// within the global scope, followed by the module's own dependencies.
+  pub unsafe fn resolve_import(&self, name: &str) -> Option<*const ()> {
+    let name = CString::new(name).ok()?;
+
+    let address = libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr());
+    if !address.is_null() {
+      return Some(address as *const ());
+    }
+
+    self.lookup(&name).map(|address| address as *const ())
+  }
+
+  /// Resolves an exported symbol using the dynamic linker.
+  unsafe fn dynamic_symbol(&self, name: &str) -> Option<*const ()> {
+    let name = CString::new(name).ok()?;
+
+    // The lookup includes the module's dependencies
+    self
+      .lookup(&name)
+      .filter(|&address| self.contains(address))
+      .map(|address| address as *const ())
+  }
+
+  /// Looks up a symbol within the module and its dependencies, using the
+  /// dynamic linker.
+  unsafe fn lookup(&self, name: &CStr) -> Option<usize> {
+    let path = CString::new(self.path.as_str()).ok()?;
+    let path = if self.path.is_empty() {
+      ptr::null()
+    } else {
+      path.as_ptr()
+    };
+
+    let handle = libc::dlopen(path, libc::RTLD_LAZY | libc::RTLD_NOLOAD);
+    if handle.is_null() {
+      return None;
+    }
+
+    let address = libc::dlsym(handle, name.as_ptr()) as usize;
+    libc::dlclose(handle);
+    Some(address).filter(|&address| address != 0)
+  }
+
+  /// Looks up a symbol in the `.symtab` & `.dynsym` sections of the file.
+  unsafe fn file_symbol(&self, name: &str) -> Option<*const ()> {
+    let path = if self.path.is_empty() {
+      "/proc/self/exe"
+    } else {
+      &self.path
+    };
+
+    let file = fs::read(path).ok()?;
+    let header = read::<FileHeader>(&file, 0)?;
+    if header.ident[..4] != *b"\x7FELF" {
+      return None;
+    }
+
+    let sections = (0..header.section_header_count as usize)
+      .map(|index| {
+        let offset =
+          header.section_header_offset as usize + index * mem::size_of::<SectionHeader>();
+        read::<SectionHeader>(&file, offset)
+      })
+      .collect::<Option<Vec<_>>>()?;
+
+    sections
+      .iter()
+      .filter(|section| section.kind == SHT_SYMTAB || section.kind == SHT_DYNSYM)
+      .find_map(|section| {
+        let strings = sections.get(section.link as usize)?;
+        let count = section.size as usize / mem::size_of::<Symbol>();
+
+        (0..count)
+          .filter_map(|index| {
+            read::<Symbol>(
+              &file,
+              section.offset as usize + index * mem::size_of::<Symbol>(),
+            )
+          })
+          .filter(|symbol| symbol.section != SHN_UNDEF && symbol.value != 0)
+          .find(|symbol| {
+            let offset = strings.offset as usize + symbol.name as usize;
+            file
+              .get(offset..)
+              .and_then(|bytes| bytes.split(|&byte| byte == 0).next())
+              .map_or(false, |symbol| {
+                is_symbol_named(&String::from_utf8_lossy(symbol), name)
+              })
+          })
+          .map(|symbol| self.base.wrapping_add(symbol.value as usize) as *const ())
+      })
+  }
+
+  /// Returns the address of the GOT entry used for an imported symbol.
+  pub unsafe fn import_slot(&self, symbol: &str) -> Option<*const usize> {
+    let (symbols, strings) = (
+      self.address_of(DT_SYMTAB)? as *const Symbol,
+      self.address_of(DT_STRTAB)? as *const u8,
+    );
+
+    let plt_entry_size = if self.value_of(DT_PLTREL) == Some(DT_RELA as usize) {
+      RELA_SIZE
+    } else {
+      mem::size_of::<Rel>()
+    };
+
+    let tables = [
+      (DT_JMPREL, DT_PLTRELSZ, plt_entry_size),
+      (DT_RELA, DT_RELASZ, RELA_SIZE),
+      (DT_REL, DT_RELSZ, mem::size_of::<Rel>()),
+    ];
+
+    tables
+      .iter()
+      .filter_map(|&(table, size, entry_size)| {
+        Some((self.address_of(table)?, self.value_of(size)?, entry_size))
+      })
+      .flat_map(|(table, size, entry_size)| {
+        (0..size / entry_size).map(move |index| &*((table + index * entry_size) as *const Rel))
+      })
+      .filter(|relocation| {
+        let kind = relocation_type(relocation.info);
+        kind == R_JUMP_SLOT || kind == R_GLOB_DAT
+      })
+      .find(|relocation| {
+        let entry = &*symbols.add(relocation_symbol(relocation.info));
+        CStr::from_ptr(strings.add(entry.name as usize) as *const _).to_bytes() == symbol.as_bytes()
+      })
+      .map(|relocation| self.base.wrapping_add(relocation.offset) as *const usize)
+  }
+
+  /// Returns the value of a dynamic section entry.
+  unsafe fn value_of(&self, tag: isize) -> Option<usize> {
+    let mut entry = self.dynamic?;
+    while (*entry).tag != DT_NULL {
+      if (*entry).tag == tag {
+        return Some((*entry).value);
+      }
+      entry = entry.add(1);
+    }
+    None
+  }
+
+  /// Returns the address referred to by a dynamic section entry.
+  unsafe fn address_of(&self, tag: isize) -> Option<usize> {
+    // Some loaders (e.g glibc) relocate the entries in place, whilst others
+    // leave them relative to the module's base.
+    self.value_of(tag).map(|value| {
+      if value < self.base {
+        value + self.base
+      } else {
+        value
+      }
+    })
+  }
+}
+
+/// Returns all modules loaded into the process.
+pub fn modules() -> Vec<Module> {
+  unsafe extern "C" fn callback(
+    info: *mut libc::dl_phdr_info,
+    _size: libc::size_t,
+    data: *mut c_void,
+  ) -> c_int {
+    let modules = &mut *(data as *mut Vec<Module>);
+    let info = &*info;
+
+    let headers = slice::from_raw_parts(info.dlpi_phdr as *const Phdr, info.dlpi_phnum as usize);
+    let dynamic = headers
+      .iter()
+      .find(|header| header.p_type == libc::PT_DYNAMIC)
+      .map(|header| {
+        (info.dlpi_addr as usize).wrapping_add(header.p_vaddr as usize) as *const Dynamic
+      });
+
+    let segments = headers
+      .iter()
+      .filter(|header| header.p_type == libc::PT_LOAD)
+      .map(|header| {
+        let start = (info.dlpi_addr as usize).wrapping_add(header.p_vaddr as usize);
+        start..start + header.p_memsz as usize
+      })
+      .collect();
+
+    let path = if info.dlpi_name.is_null() {
+      String::new()
+    } else {
+      CStr::from_ptr(info.dlpi_name)
+        .to_string_lossy()
+        .into_owned()
+    };
+
+    modules.push(Module {
+      path,
+      base: info.dlpi_addr as usize,
+      dynamic,
+      segments,
+    });
+    0
+  }
+
+  let mut modules = Vec::new();
+  unsafe {
+    libc::dl_iterate_phdr(
+      Some(callback),
+      &mut modules as *mut Vec<Module> as *mut c_void,
+    )
+  };
+  modules
+}
+
+/// Returns a loaded module by its name.
+pub fn module(name: &str) -> Result<Module> {
+  modules()
+    .into_iter()
+    .find(|module| module.is_named(name))
+    .ok_or(Error::ModuleNotFound)
+}
+
+/// Returns the address of a module's symbol.
+pub unsafe fn symbol(module: &str, name: &str) -> Result<*const ()> {
+  self::module(module)?
+    .symbol(name)
+    .ok_or(Error::SymbolNotFound)
+}
+
+/// Returns whether a raw symbol name matches a name, which may be demangled.
+///
+/// Rust symbols (both legacy & v0) match with or without their hash, whilst
+/// C++ symbols match with or without their parameters.
+fn is_symbol_named(symbol: &str, name: &str) -> bool {
+  if symbol == name {
+    return true;
+  }
+
+  if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+    if format!("{:#}", demangled) == name || demangled.to_string() == name {
+      return true;
+    }
+  }
+
+  cpp_demangle::Symbol::new(symbol)
+    .ok()
+    .and_then(|symbol| symbol.demangle(&Default::default()).ok())
+    .map_or(false, |demangled| {
+      demangled == name || demangled.split('(').next() == Some(name)
+    })
+}
+
+/// Reads a value from a file's contents, if it's within bounds.
+fn read<T>(data: &[u8], offset: usize) -> Option<T> {
+  let bytes = data.get(offset..offset.checked_add(mem::size_of::<T>())?)?;
+  Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
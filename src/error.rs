@@ -12,21 +12,40 @@ pub enum Error {
   /// The address for the target and detour are identical
   SameAddress,
   /// The address does not contain valid instructions.
-  InvalidCode,
+  InvalidCode(FaultingInstruction),
   /// The address has no available area for patching.
-  NoPatchArea,
+  NoPatchArea {
+    /// The amount of bytes available at the target.
+    available: usize,
+    /// The amount of bytes required for the patch.
+    required: usize,
+  },
   /// The address is not executable memory.
   NotExecutable,
   /// The detour is not initialized.
   NotInitialized,
   /// The detour is already initialized.
   AlreadyInitialized,
+  /// The detour is in use (e.g enabled within a scope).
+  InUse,
   /// The system is out of executable memory.
   OutOfMemory,
   /// The address contains an instruction that prevents detouring.
-  UnsupportedInstruction,
+  UnsupportedInstruction(FaultingInstruction),
   /// A memory operation failed.
   RegionFailure(region::Error),
+  /// The process's other threads could not be suspended.
+  SuspendFailure,
+  /// The module is not loaded.
+  ModuleNotFound,
+  /// The symbol could not be found.
+  SymbolNotFound,
+  /// The signature pattern is malformed.
+  InvalidPattern,
+  /// The signature pattern has no match.
+  PatternNotFound,
+  /// The signature pattern has more than one match.
+  AmbiguousPattern,
 }
 
 impl StdError for Error {
@@ -43,18 +62,64 @@ impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       Error::SameAddress => write!(f, "Target and detour address is the same"),
-      Error::InvalidCode => write!(f, "Address contains invalid assembly"),
-      Error::NoPatchArea => write!(f, "Cannot find an inline patch area"),
+      Error::InvalidCode(instruction) => {
+        write!(f, "Address contains invalid assembly: {}", instruction)
+      },
+      Error::NoPatchArea {
+        available,
+        required,
+      } => write!(
+        f,
+        "Cannot find an inline patch area ({} of {} bytes available)",
+        available, required
+      ),
       Error::NotExecutable => write!(f, "Address is not executable"),
       Error::NotInitialized => write!(f, "Detour is not initialized"),
       Error::AlreadyInitialized => write!(f, "Detour is already initialized"),
+      Error::InUse => write!(f, "Detour is still in use"),
       Error::OutOfMemory => write!(f, "Cannot allocate memory"),
-      Error::UnsupportedInstruction => write!(f, "Address contains an unsupported instruction"),
+      Error::UnsupportedInstruction(instruction) => write!(
+        f,
+        "Address contains an unsupported instruction: {}",
+        instruction
+      ),
       Error::RegionFailure(ref error) => write!(f, "{}", error),
+      Error::SuspendFailure => write!(f, "Cannot suspend the process's threads"),
+      Error::ModuleNotFound => write!(f, "Cannot find the module"),
+      Error::SymbolNotFound => write!(f, "Cannot find the symbol"),
+      Error::InvalidPattern => write!(f, "Signature pattern is malformed"),
+      Error::PatternNotFound => write!(f, "Cannot find the signature pattern"),
+      Error::AmbiguousPattern => write!(f, "Signature pattern has several matches"),
     }
   }
 }
 
+/// An instruction that prevents a target from being detoured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultingInstruction {
+  /// The address of the instruction.
+  pub address: usize,
+  /// The offset of the instruction from the target.
+  pub offset: usize,
+  /// The instruction's bytes.
+  pub bytes: Vec<u8>,
+  /// The instruction's mnemonic (e.g `loop`).
+  pub mnemonic: String,
+  /// The instruction's disassembly (e.g `loop 0x401000`).
+  pub disassembly: String,
+}
+
+impl fmt::Display for FaultingInstruction {
+  /// Output the instruction's location, bytes and disassembly.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:#x} (target+{:#x}):", self.address, self.offset)?;
+    for byte in &self.bytes {
+      write!(f, " {:02x}", byte)?;
+    }
+    write!(f, " ({})", self.disassembly)
+  }
+}
+
 impl From<region::Error> for Error {
   fn from(error: region::Error) -> Self {
     Error::RegionFailure(error)
@@ -0,0 +1,39 @@
+//! Registration of the hooks declared using the `hook` attribute.
+use crate::error::Result;
+use crate::{Function, HookableWith};
+
+/// A hook declared using the `hook` attribute.
+#[doc(hidden)]
+pub struct Hook {
+  install: unsafe fn() -> Result<()>,
+}
+
+impl Hook {
+  /// Creates a new hook registration.
+  pub const fn new(install: unsafe fn() -> Result<()>) -> Self {
+    Hook { install }
+  }
+}
+
+inventory::collect!(Hook);
+
+/// Enables every hook declared using the [hook](./attr.hook.html) attribute.
+///
+/// Each hook is initialized the first time it's installed, and hooks that
+/// are already enabled are left as is. It stops at the first hook that
+/// cannot be enabled, returning its error.
+pub unsafe fn install_all() -> Result<()> {
+  for hook in inventory::iter::<Hook> {
+    (hook.install)()?;
+  }
+  Ok(())
+}
+
+/// Asserts that a target can be detoured by a function.
+pub fn assert_hookable<T: HookableWith<D>, D: Function>() {}
+
+/// Looks up a module's symbol as a function.
+#[cfg(target_os = "linux")]
+pub unsafe fn symbol<T: Function>(module: &str, symbol: &str) -> Result<T> {
+  Ok(T::from_ptr(crate::elf::symbol(module, symbol)?))
+}
@@ -0,0 +1,43 @@
+use std::ops::Range;
+
+/// A description of how a detour has been applied.
+///
+/// It's intended for diagnostics, and is obtained using the `info` method of
+/// a detour.
+#[derive(Debug, Clone)]
+pub struct DetourInfo {
+  /// The address of the hooked function.
+  pub target: *const (),
+  /// The address range replaced when the detour is enabled.
+  pub patch_area: Range<usize>,
+  /// The bytes of the patch area, as they are when the detour is disabled.
+  pub original_bytes: Vec<u8>,
+  /// The bytes of the patch area, as they are when the detour is enabled.
+  pub patched_bytes: Vec<u8>,
+  /// The amount of the target's bytes that were relocated to the trampoline.
+  pub prolog_size: usize,
+  /// The address of the trampoline.
+  pub trampoline: *const (),
+  /// The size of the trampoline.
+  pub trampoline_size: usize,
+  /// The address of the relay, if the detour is out of range for a jump.
+  pub relay: Option<*const ()>,
+  /// Whether the hot patch area, preceding the target, is used or not.
+  pub uses_hot_patch: bool,
+  /// The instructions relocated from the target to the trampoline.
+  pub relocated_instructions: Vec<RelocatedInstruction>,
+}
+
+/// An instruction relocated to a trampoline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelocatedInstruction {
+  /// The address of the instruction in the target.
+  pub original: *const (),
+  /// The address of the relocated instruction in the trampoline.
+  pub relocated: *const (),
+}
+
+unsafe impl Send for DetourInfo {}
+unsafe impl Sync for DetourInfo {}
+unsafe impl Send for RelocatedInstruction {}
+unsafe impl Sync for RelocatedInstruction {}
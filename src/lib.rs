@@ -1,8 +1,5 @@
 #![recursion_limit = "1024"]
-#![cfg_attr(
-  feature = "nightly",
-  feature(const_fn_trait_bound, unboxed_closures, abi_thiscall)
-)]
+#![cfg_attr(feature = "nightly", feature(abi_thiscall, abi_vectorcall, c_unwind))]
 #![cfg_attr(
   all(feature = "nightly", test),
   feature(naked_functions, core_intrinsics, asm)
@@ -25,10 +22,12 @@
 //! - Detects NOP-padding.
 //! - Relay for large offsets (>2GB).
 //! - Supports hot patching.
+//! - Suspends & relocates threads executing patched code (Linux).
+//! - Never exposes partially written instructions (Linux).
 //!
 //! ## Detours
 //!
-//! Three different types of detours are provided:
+//! Five different types of detours are provided:
 //!
 //! - [Static](./struct.StaticDetour.html): A static & type-safe interface.
 //!   Thanks to its static nature it can accept a closure as its detour, but is
@@ -36,18 +35,75 @@
 //!
 //! - [Generic](./struct.GenericDetour.html): A type-safe interface — the same
 //!   prototype is enforced for both the target and the detour. It is also
-//!   enforced when invoking the original target.
+//!   enforced when invoking the original target. A closure can also be used as
+//!   its detour, through a thunk allocated for each hook.
 //!
 //! - [Raw](./struct.RawDetour.html): The underlying building block that the
 //!   others types abstract upon. It has no type-safety and interacts with raw
 //!   pointers. It should be avoided unless any types are references, or not
 //!   known until runtime.
 //!
+//! - [Import](./struct.ImportDetour.html): A type-safe interface, which
+//!   replaces a module's GOT entry instead of patching code, so only calls made
+//!   by that module are detoured (Linux).
+//!
+//! - [VTable](./struct.VTableDetour.html): A type-safe interface, which
+//!   replaces a virtual method within a vtable, either in place or within a
+//!   copy used by a single object.
+//!
+//! Several detours can share a single hook of a target, as the ordered links
+//! of a [HookChain](./struct.HookChain.html).
+//!
+//! Code can also be hooked at any instruction, with access to all registers,
+//! using a [MidHook](./struct.MidHook.html).
+//!
+//! C variadic functions can be detoured as well, with their variadic arguments
+//! accessible through [VariadicArgs](./struct.VariadicArgs.html).
+//!
+//! On Linux, targets can also be looked up by their symbol name (e.g using
+//! [RawDetour::from_symbol](./struct.RawDetour.html#method.from_symbol)),
+//! including functions which are not exported.
+//!
+//! Targets without any symbols can be located by their bytes, using the
+//! [signature](./signature/index.html) module.
+//!
+//! Targets obtained through jump thunks (e.g PLT stubs) can be resolved using
+//! [follow_jumps](./fn.follow_jumps.html).
+//!
+//! Whether a function can be hooked, and how, can be determined beforehand
+//! using [analyze](./fn.analyze.html).
+//!
+//! Calls to a target made from within its own detour closure can be forwarded
+//! to the original function, using a reentrancy guard (see
+//! `set_reentrancy_guard`). Detour functions can do so themselves, using
+//! `enter`.
+//!
+//! Inline detours can also be restricted to the threads which opt in, without
+//! patching any code per thread (see `set_per_thread`).
+//!
+//! To ensure a detour is disabled again, even if a panic occurs, it can be
+//! enabled within a scope (see `enable_scoped` and `with_enabled`).
+//!
+//! A panic within a detour's closure never unwinds into the caller, unless it
+//! is allowed to; the [PanicPolicy](./enum.PanicPolicy.html) of a hook decides
+//! how it is handled instead.
+//!
+//! Hooks can also be declared using the [hook](./attr.hook.html) attribute,
+//! and enabled all at once using [install_all](./fn.install_all.html).
+//!
+//! Every inline detour is tracked by the process-wide
+//! [registry](./registry/index.html), which lists them and reports their
+//! changes.
+//!
+//! Any mix of detours can be toggled atomically using a
+//! [DetourTransaction](./struct.DetourTransaction.html).
+//!
 //! ## Features
 //!
-//! - **nightly**: Enabled by default. Required for static detours, due to usage
-//!   of *const_fn* & *unboxed_closures*.   The feature also enables a more
-//!   extensive test suite.
+//! - **nightly**: Enabled by default. Required for detouring `thiscall`,
+//!   `vectorcall` and `C-unwind` functions, due to usage of *abi_thiscall*,
+//!   *abi_vectorcall* & *c_unwind*. The feature also enables a more extensive
+//!   test suite.
 //!
 //! ## Platforms
 //!
@@ -94,9 +150,105 @@
 //! function can be called regardless whether the function is hooked or not.
 
 // Re-exports
+pub use arch::{analyze, follow_jumps, Context, HookPlan, PatchStrategy};
 pub use detours::*;
-pub use error::{Error, Result};
-pub use traits::{Function, HookableWith};
+pub use error::{Error, FaultingInstruction, Result};
+pub use hooks::install_all;
+pub use info::{DetourInfo, RelocatedInstruction};
+pub use panic::PanicPolicy;
+pub use reentrancy::ReentrancyGuard;
+pub use traits::{AnyDetour, Function, HookableWith};
+pub use transaction::DetourTransaction;
+pub use variadic::{VaArgument, VaList, VariadicArgs};
+
+/// Declares a function as the detour of a hook.
+///
+/// A [StaticDetour](./struct.StaticDetour.html) is defined with the function's
+/// name, and the function becomes its detour. Within it, the original
+/// function can be invoked using `original(...)`, so no argument may be named
+/// `original`.
+///
+/// The target is either a function (`target = path`), or a symbol looked up
+/// when the hook is installed (`symbol = "name"`, with an optional `module`,
+/// which defaults to the main executable; Linux only). The hook is
+/// registered, so it's enabled by [install_all](./fn.install_all.html).
+///
+/// The static detour has the `unsafe` counterpart of the function's signature,
+/// so a safe function may detour an `unsafe` target (see
+/// [HookableWith](./trait.HookableWith.html)). A `target` must coerce to it,
+/// which is checked at compile time. A `symbol` is assumed to match it,
+/// without any checks.
+///
+/// # Example
+///
+/// ```rust
+/// use detour::hook;
+///
+/// #[inline(never)]
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// #[hook(target = add5)]
+/// fn add10(val: i32) -> i32 {
+///   original(val) + 5
+/// }
+///
+/// fn main() -> detour::Result<()> {
+///   unsafe { detour::install_all()? };
+///
+///   assert!(add10.is_enabled());
+///   assert_eq!(add5(1), 11);
+///   Ok(())
+/// }
+/// ```
+///
+/// A target with a different signature is rejected:
+///
+/// ```compile_fail
+/// # use detour::hook;
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// #[hook(target = add5)]
+/// fn add10(val: i64) -> i64 {
+///   original(val) + 5
+/// }
+/// # fn main() {}
+/// ```
+///
+/// As is an argument named `original`:
+///
+/// ```compile_fail
+/// # use detour::hook;
+/// # fn add5(val: i32) -> i32 {
+/// #   val + 5
+/// # }
+/// #[hook(target = add5)]
+/// fn add10(original: i32) -> i32 {
+///   original + 10
+/// }
+/// # fn main() {}
+/// ```
+///
+/// Hooking a library's function by its symbol:
+///
+/// ```ignore
+/// #[detour::hook(module = "libc.so.6", symbol = "open")]
+/// unsafe extern "C" fn my_open(path: *const c_char, flags: c_int) -> c_int {
+///   original(path, flags)
+/// }
+/// ```
+pub use detour_macros::hook;
+
+#[doc(hidden)]
+pub mod __private {
+  #[cfg(target_os = "linux")]
+  pub use crate::hooks::symbol;
+  pub use crate::hooks::{assert_hookable, Hook};
+  pub use inventory;
+}
 
 #[macro_use]
 mod macros;
@@ -105,10 +257,22 @@ mod macros;
 mod alloc;
 mod arch;
 mod detours;
+#[cfg(target_os = "linux")]
+mod elf;
 mod error;
+mod hooks;
+mod info;
+mod panic;
 mod pic;
+mod reentrancy;
+pub mod registry;
+pub mod signature;
+mod thread;
+mod tls;
 mod traits;
+mod transaction;
 mod util;
+mod variadic;
 
 #[cfg(test)]
 mod tests {
@@ -30,7 +30,6 @@
 /// }
 /// # fn main() { }
 /// ```
-#[cfg(feature = "nightly")]
 #[macro_export]
 // Inspired by: https://github.com/Jascha-N/minhook-rs
 macro_rules! static_detour {
@@ -126,13 +125,14 @@ macro_rules! static_detour {
       $($visibility)* static $name: $crate::StaticDetour<$fn_type> = {
         #[inline(never)]
         #[allow(unused_unsafe)]
+        #[allow(clippy::too_many_arguments)]
         $($modifier) * fn __ffi_detour(
             $($argument_name: $argument_type),*) -> $return_type {
           #[allow(unused_unsafe)]
-          ($name.__detour())($($argument_name),*)
+          $name.__detour($($argument_name),*)
         }
 
-        $crate::StaticDetour::__new(__ffi_detour)
+        $crate::StaticDetour::__new(__ffi_detour, concat!(module_path!(), "::", stringify!($name)))
       };
     );
   };
@@ -140,8 +140,9 @@ macro_rules! static_detour {
   // Associates each argument type with a dummy name.
   (@argument_names ($label:ident) ($($input:tt)*) ($($token:tt)*)) => {
     static_detour!(@argument_names ($label) ($($input)*)(
-      __arg_0  __arg_1  __arg_2  __arg_3  __arg_4  __arg_5  __arg_6
-      __arg_7  __arg_8  __arg_9  __arg_10 __arg_11 __arg_12 __arg_13
+      __arg_0  __arg_1  __arg_2  __arg_3  __arg_4  __arg_5  __arg_6  __arg_7
+      __arg_8  __arg_9  __arg_10 __arg_11 __arg_12 __arg_13 __arg_14 __arg_15
+      __arg_16 __arg_17 __arg_18 __arg_19 __arg_20 __arg_21 __arg_22 __arg_23
     )($($token)*)());
   };
   (@argument_names
@@ -176,37 +177,57 @@ macro_rules! impl_hookable {
   };
 
   (@impl_all ($($nm:ident : $ty:ident),*)) => {
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (                  fn($($ty),*) -> Ret));
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "cdecl"    fn($($ty),*) -> Ret));
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "stdcall"  fn($($ty),*) -> Ret));
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "fastcall" fn($($ty),*) -> Ret));
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "win64"    fn($($ty),*) -> Ret));
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "C"        fn($($ty),*) -> Ret));
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "system"   fn($($ty),*) -> Ret));
+    impl_hookable!(@impl_pair ($($nm : $ty),*) ());
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "C"));
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "system"));
 
+    #[cfg(target_arch = "x86")]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "cdecl"));
+
+    // Windows accepts the x86 conventions on any architecture
+    #[cfg(any(target_arch = "x86", windows))]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "stdcall"));
+    #[cfg(any(target_arch = "x86", windows))]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "fastcall"));
+
+    #[cfg(target_arch = "x86_64")]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "win64"));
+    #[cfg(target_arch = "x86_64")]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "sysv64"));
+
+    #[cfg(all(feature = "nightly", target_arch = "x86"))]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "thiscall"));
+    #[cfg(feature = "nightly")]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "vectorcall"));
     #[cfg(feature = "nightly")]
-    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "thiscall" fn($($ty),*) -> Ret));
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "C-unwind"));
+    #[cfg(feature = "nightly")]
+    impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "system-unwind"));
+
+    impl_hookable!(@impl_variadic ($($nm : $ty),*));
   };
 
-  (@impl_pair ($($nm:ident : $ty:ident),*) ($($fn_t:tt)*)) => {
-    impl_hookable!(@impl_fun ($($nm : $ty),*) ($($fn_t)*) (unsafe $($fn_t)*));
+  (@impl_pair ($($nm:ident : $ty:ident),*) ($($abi:tt)*)) => {
+    impl_hookable!(@impl_fun ($($nm : $ty),*) ($($abi)*) (unsafe $($abi)*));
   };
 
-  (@impl_fun ($($nm:ident : $ty:ident),*) ($safe_type:ty) ($unsafe_type:ty)) => {
-    impl_hookable!(@impl_core ($($nm : $ty),*) ($safe_type));
-    impl_hookable!(@impl_core ($($nm : $ty),*) ($unsafe_type));
+  (@impl_fun ($($nm:ident : $ty:ident),*) ($($safe:tt)*) ($($unsafe:tt)*)) => {
+    impl_hookable!(@impl_core ($($nm : $ty),*) ($($safe)*) ($($safe)* fn($($ty),*) -> Ret));
+    impl_hookable!(@impl_core ($($nm : $ty),*) ($($unsafe)*) ($($unsafe)* fn($($ty),*) -> Ret));
 
-    impl_hookable!(@impl_unsafe ($($nm : $ty),*) ($unsafe_type) ($safe_type));
-    impl_hookable!(@impl_safe ($($nm : $ty),*) ($safe_type));
+    impl_hookable!(@impl_unsafe ($($nm : $ty),*)
+      ($($unsafe)* fn($($ty),*) -> Ret) ($($safe)* fn($($ty),*) -> Ret));
+    impl_hookable!(@impl_safe ($($nm : $ty),*) ($($safe)* fn($($ty),*) -> Ret));
   };
 
   (@impl_unsafe ($($nm:ident : $ty:ident),*) ($target:ty) ($detour:ty)) => {
     unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWith<$detour> for $target {}
 
-    #[cfg(feature = "nightly")]
     impl<Ret: 'static, $($ty: 'static),*> $crate::StaticDetour<$target> {
       #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
       pub unsafe fn call(&self, $($nm : $ty),*) -> Ret {
+        let _call = self.track_call();
         let original: $target = ::std::mem::transmute(self.trampoline().expect("calling detour trampoline"));
         original($($nm),*)
       }
@@ -214,18 +235,47 @@ macro_rules! impl_hookable {
 
     impl<Ret: 'static, $($ty: 'static),*> $crate::GenericDetour<$target> {
       #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
+      pub unsafe fn call(&self, $($nm : $ty),*) -> Ret {
+        let original: $target = ::std::mem::transmute(self.trampoline());
+        original($($nm),*)
+      }
+    }
+
+    #[cfg(target_os = "linux")]
+    impl<Ret: 'static, $($ty: 'static),*> $crate::ImportDetour<$target> {
+      #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
       pub unsafe fn call(&self, $($nm : $ty),*) -> Ret {
         let original: $target = ::std::mem::transmute(self.trampoline());
         original($($nm),*)
       }
     }
+
+    impl<Ret: 'static, $($ty: 'static),*> $crate::VTableDetour<$target> {
+      #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
+      pub unsafe fn call(&self, $($nm : $ty),*) -> Ret {
+        let original: $target = ::std::mem::transmute(self.trampoline());
+        original($($nm),*)
+      }
+    }
+
+    impl<'a, Ret: 'static, $($ty: 'static),*> $crate::Next<'a, $target> {
+      #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
+      pub unsafe fn call(&self, $($nm : $ty),*) -> Ret {
+        self.invoke(($($nm,)*))
+      }
+    }
   };
 
   (@impl_safe ($($nm:ident : $ty:ident),*) ($fn_type:ty)) => {
-    #[cfg(feature = "nightly")]
     impl<Ret: 'static, $($ty: 'static),*> $crate::StaticDetour<$fn_type> {
       #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
       pub fn call(&self, $($nm : $ty),*) -> Ret {
+        let _call = self.track_call();
         unsafe {
           let original: $fn_type = ::std::mem::transmute(self.trampoline().expect("calling detour trampoline"));
           original($($nm),*)
@@ -235,6 +285,7 @@ macro_rules! impl_hookable {
 
     impl<Ret: 'static, $($ty: 'static),*> $crate::GenericDetour<$fn_type> {
       #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
       pub fn call(&self, $($nm : $ty),*) -> Ret {
         unsafe {
           let original: $fn_type = ::std::mem::transmute(self.trampoline());
@@ -242,12 +293,167 @@ macro_rules! impl_hookable {
         }
       }
     }
+
+    #[cfg(target_os = "linux")]
+    impl<Ret: 'static, $($ty: 'static),*> $crate::ImportDetour<$fn_type> {
+      #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
+      pub fn call(&self, $($nm : $ty),*) -> Ret {
+        unsafe {
+          let original: $fn_type = ::std::mem::transmute(self.trampoline());
+          original($($nm),*)
+        }
+      }
+    }
+
+    impl<Ret: 'static, $($ty: 'static),*> $crate::VTableDetour<$fn_type> {
+      #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
+      pub fn call(&self, $($nm : $ty),*) -> Ret {
+        unsafe {
+          let original: $fn_type = ::std::mem::transmute(self.trampoline());
+          original($($nm),*)
+        }
+      }
+    }
+
+    impl<'a, Ret: 'static, $($ty: 'static),*> $crate::Next<'a, $fn_type> {
+      #[doc(hidden)]
+      #[allow(clippy::too_many_arguments)]
+      pub fn call(&self, $($nm : $ty),*) -> Ret {
+        self.invoke(($($nm,)*))
+      }
+    }
+  };
+
+  (@impl_core ($($nm:ident : $ty:ident),*) ($($modifier:tt)*) ($fn_type:ty)) => {
+    unsafe impl<Ret: 'static, $($ty: 'static),*> Function for $fn_type {
+      type Arguments = ($($ty,)*);
+      type Output = Ret;
+      type Closure = dyn Fn($($ty),*) -> Ret + Send + Sync;
+
+      const UNWINDS: bool = impl_hookable!(@unwinds $($modifier)*);
+
+      unsafe fn from_ptr(ptr: *const ()) -> Self {
+        ::std::mem::transmute(ptr)
+      }
+
+      fn to_ptr(&self) -> *const () {
+        unsafe { ::std::mem::transmute(*self) }
+      }
+    }
+
+    impl<Ret: 'static, $($ty: 'static),*> $crate::StaticDetour<$fn_type> {
+      #[doc(hidden)]
+      pub unsafe fn initialize<Closure>(
+        &self,
+        target: $fn_type,
+        closure: Closure,
+      ) -> $crate::Result<&Self>
+      where
+        Closure: Fn($($ty),*) -> Ret + Send + Sync + 'static,
+      {
+        self.initialize_boxed(target, Box::new(closure))
+      }
+
+      #[doc(hidden)]
+      pub fn set_detour<Closure>(&self, closure: Closure)
+      where
+        Closure: Fn($($ty),*) -> Ret + Send + Sync + 'static,
+      {
+        self.set_boxed_detour(Box::new(closure))
+      }
+
+      #[doc(hidden)]
+      #[allow(unused_unsafe)]
+      #[allow(clippy::too_many_arguments)]
+      pub fn __detour(&self, $($nm : $ty),*) -> Ret {
+        match self.enter_detour() {
+          Some(detour) => {
+            $crate::panic::catch(($($nm,)*), |($($nm,)*)| (detour.closure)($($nm),*))
+              .unwrap_or_else(|panic| self.recover(panic, |($($nm,)*)| unsafe { self.call($($nm),*) }))
+          },
+          None => {
+            let _call = self.track_call();
+            unsafe {
+              let original: $fn_type = ::std::mem::transmute(self.trampoline().expect("calling detour trampoline"));
+              original($($nm),*)
+            }
+          },
+        }
+      }
+    }
+
+    impl<Ret: 'static, $($ty: 'static),*> $crate::GenericDetour<$fn_type> {
+      #[doc(hidden)]
+      pub unsafe fn with_closure<Closure>(target: $fn_type, closure: Closure) -> $crate::Result<Self>
+      where
+        Closure: Fn($($ty),*) -> Ret + Send + Sync + 'static,
+      {
+        Self::with_boxed_closure(target, Box::new(closure), Self::__closure_shim as *const (), false)
+      }
+
+      #[allow(unused_unsafe)]
+      #[allow(clippy::too_many_arguments)]
+      $($modifier)* fn __closure_shim($($nm : $ty),*) -> Ret {
+        let context = unsafe { Self::current_context() };
+        let _guard = context.enter();
+        $crate::panic::catch(($($nm,)*), |($($nm,)*)| context.closure()($($nm),*))
+          .unwrap_or_else(|panic| context.recover(panic, |($($nm,)*)| unsafe {
+            let original: $fn_type = ::std::mem::transmute(context.trampoline());
+            original($($nm),*)
+          }))
+      }
+    }
+
+    impl<Ret: 'static, $($ty: 'static),*> $crate::HookChain<$fn_type> {
+      #[doc(hidden)]
+      pub unsafe fn new(target: $fn_type) -> $crate::Result<Self> {
+        let state = $crate::detours::ChainState::<$fn_type>::new(|trampoline, ($($nm,)*)| unsafe {
+          let original: $fn_type = ::std::mem::transmute(trampoline);
+          original($($nm),*)
+        });
+
+        let dispatcher = state.clone();
+        let detour = $crate::GenericDetour::<$fn_type>::with_closure(target, move |$($nm),*| {
+          dispatcher.dispatch(($($nm,)*))
+        })?;
+        Ok(Self::from_parts(detour, state))
+      }
+
+      #[doc(hidden)]
+      pub fn insert<Closure>(&self, priority: i32, closure: Closure) -> $crate::LinkId
+      where
+        Closure: Fn(&$crate::Next<$fn_type>, $($ty),*) -> Ret + Send + Sync + 'static,
+      {
+        self.insert_boxed(priority, Box::new(move |next, ($($nm,)*)| closure(next, $($nm),*)))
+      }
+    }
   };
 
-  (@impl_core ($($nm:ident : $ty:ident),*) ($fn_type:ty)) => {
+  // Whether a panic may unwind out of a function with a calling convention
+  (@unwinds unsafe $($abi:tt)*) => { impl_hookable!(@unwinds $($abi)*) };
+  (@unwinds) => { true };
+  (@unwinds extern "C-unwind") => { true };
+  (@unwinds extern "system-unwind") => { true };
+  (@unwinds $($abi:tt)*) => { false };
+
+  // C variadic functions require at least one fixed argument
+  (@impl_variadic ()) => {};
+  (@impl_variadic ($($nm:ident : $ty:ident),+)) => {
+    impl_hookable!(@impl_variadic_core ($($nm : $ty),*) () (extern "C" fn($($ty),*, ...) -> Ret));
+    impl_hookable!(@impl_variadic_core ($($nm : $ty),*) (unsafe)
+      (unsafe extern "C" fn($($ty),*, ...) -> Ret));
+
+    unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWith<extern "C" fn($($ty),*, ...) -> Ret>
+      for unsafe extern "C" fn($($ty),*, ...) -> Ret {}
+  };
+
+  (@impl_variadic_core ($($nm:ident : $ty:ident),*) ($($modifier:tt)*) ($fn_type:ty)) => {
     unsafe impl<Ret: 'static, $($ty: 'static),*> Function for $fn_type {
       type Arguments = ($($ty,)*);
       type Output = Ret;
+      type Closure = dyn Fn($($ty,)* &$crate::VariadicArgs) -> Ret + Send + Sync;
 
       unsafe fn from_ptr(ptr: *const ()) -> Self {
         ::std::mem::transmute(ptr)
@@ -257,6 +463,40 @@ macro_rules! impl_hookable {
         unsafe { ::std::mem::transmute(*self) }
       }
     }
+
+    impl<Ret: 'static, $($ty: 'static),*> $crate::GenericDetour<$fn_type> {
+      #[doc(hidden)]
+      #[allow(unused_unsafe)]
+      #[allow(clippy::too_many_arguments)]
+      pub $($modifier)* fn call(&self, $($nm : $ty,)* arguments: &$crate::VariadicArgs) -> Ret {
+        let mut arguments = arguments.rewind();
+        $(arguments.push($nm);)*
+        unsafe { arguments.forward(self.trampoline()) }
+      }
+
+      #[doc(hidden)]
+      pub unsafe fn with_closure<Closure>(target: $fn_type, closure: Closure) -> $crate::Result<Self>
+      where
+        Closure: Fn($($ty,)* &$crate::VariadicArgs) -> Ret + Send + Sync + 'static,
+      {
+        Self::with_boxed_closure(target, Box::new(closure), Self::__variadic_shim as *const (), true)
+      }
+
+      #[allow(clippy::too_many_arguments)]
+      extern "C" fn __variadic_shim($($nm : $ty),*) -> Ret {
+        let context = unsafe { Self::current_context() };
+        let _guard = context.enter();
+        let mut arguments = $crate::VariadicArgs::current();
+        $(arguments.skip::<$ty>();)*
+
+        $crate::panic::catch(($($nm,)*), |($($nm,)*)| context.closure()($($nm,)* &arguments))
+          .unwrap_or_else(|panic| context.recover(panic, |($($nm,)*)| {
+            let mut arguments = arguments.rewind();
+            $(arguments.push($nm);)*
+            unsafe { arguments.forward(context.trampoline()) }
+          }))
+      }
+    }
   };
 
   ($($nm:ident : $ty:ident),*) => {
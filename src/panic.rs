@@ -0,0 +1,101 @@
+//! Handling of panics within detour closures.
+use crate::Function;
+use std::any::Any;
+use std::mem::{self, ManuallyDrop};
+use std::panic::{self, AssertUnwindSafe};
+use std::{fmt, process, ptr};
+
+/// How a panic within a detour's closure is handled.
+///
+/// Unwinding from a detour into the frames of foreign code is undefined
+/// behaviour, so any panic is caught before it leaves the closure. By default,
+/// the process is aborted.
+pub enum PanicPolicy<Ret> {
+  /// Aborts the process, after printing a message naming the hook.
+  Abort,
+  /// Calls the original function, and returns its result.
+  ///
+  /// It receives a copy of the arguments, so the process is aborted instead if
+  /// any of them has a destructor.
+  CallOriginal,
+  /// Returns the result of a function.
+  Fallback(Box<dyn Fn() -> Ret + Send + Sync>),
+  /// Continues unwinding.
+  ///
+  /// Only functions which may unwind (i.e Rust and `C-unwind` functions)
+  /// support this, the process is aborted otherwise.
+  Propagate,
+}
+
+impl<Ret> PanicPolicy<Ret> {
+  /// Handles a panic caught within a hook's detour.
+  pub(crate) fn recover<T, F>(
+    &self,
+    hook: &dyn fmt::Display,
+    panic: Panic<T::Arguments>,
+    original: F,
+  ) -> Ret
+  where
+    T: Function<Output = Ret>,
+    F: FnOnce(T::Arguments) -> Ret,
+  {
+    match self {
+      PanicPolicy::CallOriginal if !mem::needs_drop::<T::Arguments>() => {
+        original(ManuallyDrop::into_inner(panic.arguments))
+      },
+      PanicPolicy::Fallback(fallback) => panic::catch_unwind(AssertUnwindSafe(fallback))
+        .unwrap_or_else(|payload| abort(hook, &*payload)),
+      PanicPolicy::Propagate if T::UNWINDS => panic::resume_unwind(panic.payload),
+      _ => abort(hook, &*panic.payload),
+    }
+  }
+}
+
+impl<Ret> Default for PanicPolicy<Ret> {
+  fn default() -> Self {
+    PanicPolicy::Abort
+  }
+}
+
+impl<Ret> fmt::Debug for PanicPolicy<Ret> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PanicPolicy::Abort => write!(f, "Abort"),
+      PanicPolicy::CallOriginal => write!(f, "CallOriginal"),
+      PanicPolicy::Fallback(_) => write!(f, "Fallback(..)"),
+      PanicPolicy::Propagate => write!(f, "Propagate"),
+    }
+  }
+}
+
+/// A panic caught within a detour's closure.
+pub(crate) struct Panic<A> {
+  payload: Box<dyn Any + Send>,
+  /// A copy of the arguments consumed by the closure.
+  arguments: ManuallyDrop<A>,
+}
+
+/// Invokes a detour's closure, catching any panic.
+pub(crate) fn catch<A, R, F>(arguments: A, closure: F) -> Result<R, Panic<A>>
+where
+  F: FnOnce(A) -> R,
+{
+  // The arguments are kept in case the original function is called
+  let arguments = ManuallyDrop::new(arguments);
+  let copy = unsafe { ptr::read(&*arguments) };
+
+  panic::catch_unwind(AssertUnwindSafe(|| closure(copy)))
+    .map_err(|payload| Panic { payload, arguments })
+}
+
+/// Aborts the process, due to a panic within a hook's detour.
+fn abort(hook: &dyn fmt::Display, payload: &(dyn Any + Send)) -> ! {
+  let message = payload
+    .downcast_ref::<&str>()
+    .copied()
+    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+    .unwrap_or("Box<dyn Any>");
+
+  eprintln!("detour {} panicked: {}, aborting", hook, message);
+  process::abort()
+}
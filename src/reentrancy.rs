@@ -0,0 +1,78 @@
+//! Tracking of the detours executed by each thread.
+use crate::tls::Keys;
+use std::marker::PhantomData;
+
+thread_local! {
+  /// The detours the current thread is executing.
+  static EXECUTING: Keys = const { Keys::new() };
+}
+
+/// A guard marking the current thread as executing a detour.
+///
+/// The mark is removed once the guard is dropped.
+#[derive(Debug)]
+pub struct ReentrancyGuard {
+  key: usize,
+  // Whether the mark could be stored, or the capacity was exceeded
+  tracked: bool,
+  // The guard must be dropped on the thread it was created
+  _thread: PhantomData<*const ()>,
+}
+
+impl Drop for ReentrancyGuard {
+  fn drop(&mut self) {
+    // Removing an untracked key would remove the mark of an outer call
+    if self.tracked {
+      let _ = EXECUTING.try_with(|executing| executing.remove(self.key));
+    }
+  }
+}
+
+/// Marks the current thread as executing a detour, unless it already is.
+pub(crate) fn enter(key: usize) -> Option<ReentrancyGuard> {
+  if is_executing(key) {
+    return None;
+  }
+
+  Some(enter_nested(key))
+}
+
+/// Marks the current thread as executing a detour, regardless of whether it
+/// already is.
+pub(crate) fn enter_nested(key: usize) -> ReentrancyGuard {
+  // Any detours beyond the capacity are not tracked
+  let tracked = EXECUTING
+    .try_with(|executing| executing.insert(key))
+    .unwrap_or(false);
+
+  ReentrancyGuard {
+    key,
+    tracked,
+    _thread: PhantomData,
+  }
+}
+
+/// Returns whether the current thread is executing a detour.
+pub(crate) fn is_executing(key: usize) -> bool {
+  EXECUTING
+    .try_with(|executing| executing.contains(key))
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::mem;
+
+  #[test]
+  fn reentrancy_beyond_capacity() {
+    let key = reentrancy_beyond_capacity as *const () as usize;
+    let guards = (0..64).map(|_| enter_nested(key)).collect::<Vec<_>>();
+
+    // Only the guards which could be tracked may remove a mark
+    for guard in guards.into_iter().skip(1).rev() {
+      mem::drop(guard);
+      assert!(is_executing(key));
+    }
+  }
+}
@@ -0,0 +1,227 @@
+//! Tracking of the detours within the process.
+//!
+//! Every inline detour is registered on creation, and removed once it's
+//! dropped. The registry can be used to list the detours, to find which ones
+//! hook a function, or to be notified whenever one of them changes:
+//!
+//! ```rust
+//! # use detour::Result;
+//! use detour::registry::{self, Event};
+//! use detour::RawDetour;
+//!
+//! #[inline(never)]
+//! fn add5(val: i32) -> i32 {
+//!   val + 5
+//! }
+//!
+//! fn add10(val: i32) -> i32 {
+//!   val + 10
+//! }
+//!
+//! # fn main() -> Result<()> {
+//! let _subscription = registry::subscribe(|event, entry| {
+//!   if event == Event::Enabled {
+//!     println!("{:?} detoured to {:?}", entry.target, entry.detour);
+//!   }
+//! });
+//!
+//! let hook = unsafe { RawDetour::new(add5 as *const (), add10 as *const ())? };
+//! hook.set_label("add5");
+//! unsafe { hook.enable()? };
+//!
+//! let entries = registry::find(add5 as *const ());
+//! assert_eq!(entries.len(), 1);
+//! assert_eq!(entries[0].label.as_deref(), Some("add5"));
+//! assert!(entries[0].enabled);
+//! # Ok(())
+//! # }
+//! ```
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+
+lazy_static! {
+  /// The detours within the process, and the subscribers to their events.
+  static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+/// A callback notified of the registry's events.
+type Callback = dyn Fn(Event, &DetourEntry) + Send + Sync;
+
+#[derive(Default)]
+struct Registry {
+  /// The detours, along with the key identifying them.
+  entries: Vec<(usize, DetourEntry)>,
+  /// The subscribers, along with their identifiers.
+  subscribers: Vec<(usize, Arc<Callback>)>,
+  next_subscriber: usize,
+}
+
+/// A detour within the registry.
+#[derive(Debug, Clone)]
+pub struct DetourEntry {
+  /// The address of the hooked function.
+  pub target: *const (),
+  /// The address of the detour.
+  pub detour: *const (),
+  /// The address of the trampoline.
+  pub trampoline: *const (),
+  /// Whether the detour is enabled or not.
+  pub enabled: bool,
+  /// The label assigned to the detour, if any.
+  pub label: Option<String>,
+}
+
+unsafe impl Send for DetourEntry {}
+unsafe impl Sync for DetourEntry {}
+
+/// A change of a detour within the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+  /// The detour has been created.
+  Created,
+  /// The detour has been enabled.
+  Enabled,
+  /// The detour has been disabled.
+  Disabled,
+  /// The detour has been dropped.
+  Dropped,
+}
+
+/// A subscription to the registry's events.
+///
+/// The subscriber is no longer notified once it's dropped.
+#[must_use]
+pub struct Subscription {
+  id: usize,
+}
+
+impl Drop for Subscription {
+  fn drop(&mut self) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.subscribers.retain(|(id, _)| *id != self.id);
+  }
+}
+
+impl fmt::Debug for Subscription {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Subscription {{ id: {} }}", self.id)
+  }
+}
+
+/// Returns all detours within the process.
+pub fn detours() -> Vec<DetourEntry> {
+  let registry = REGISTRY.lock().unwrap();
+  registry
+    .entries
+    .iter()
+    .map(|(_, entry)| entry.clone())
+    .collect()
+}
+
+/// Returns the detours of a function, in the order they were created.
+pub fn find(target: *const ()) -> Vec<DetourEntry> {
+  let registry = REGISTRY.lock().unwrap();
+  registry
+    .entries
+    .iter()
+    .filter(|(_, entry)| entry.target == target)
+    .map(|(_, entry)| entry.clone())
+    .collect()
+}
+
+/// Subscribes to the events of all detours, until the subscription is
+/// dropped.
+///
+/// The callback is invoked by the thread which caused the event, after the
+/// change has been applied.
+pub fn subscribe<F>(callback: F) -> Subscription
+where
+  F: Fn(Event, &DetourEntry) + Send + Sync + 'static,
+{
+  let mut registry = REGISTRY.lock().unwrap();
+  let id = registry.next_subscriber;
+  registry.next_subscriber += 1;
+  registry.subscribers.push((id, Arc::new(callback)));
+  Subscription { id }
+}
+
+/// Registers a newly created detour.
+pub(crate) fn insert(key: usize, target: *const (), detour: *const (), trampoline: *const ()) {
+  let entry = DetourEntry {
+    enabled: false,
+    label: None,
+    target,
+    detour,
+    trampoline,
+  };
+
+  update(key, Event::Created, |entries| {
+    entries.push((key, entry));
+  });
+}
+
+/// Updates whether a detour is enabled.
+pub(crate) fn toggle(key: usize, enabled: bool) {
+  let event = if enabled {
+    Event::Enabled
+  } else {
+    Event::Disabled
+  };
+
+  update(key, event, |entries| {
+    if let Some((_, entry)) = entries.iter_mut().find(|(other, _)| *other == key) {
+      entry.enabled = enabled;
+    }
+  });
+}
+
+/// Assigns a label to a detour.
+pub(crate) fn set_label(key: usize, label: Option<String>) {
+  let mut registry = REGISTRY.lock().unwrap();
+  if let Some((_, entry)) = registry.entries.iter_mut().find(|(other, _)| *other == key) {
+    entry.label = label;
+  }
+}
+
+/// Removes a dropped detour.
+pub(crate) fn remove(key: usize) {
+  let mut registry = REGISTRY.lock().unwrap();
+  if let Some(index) = registry.entries.iter().position(|(other, _)| *other == key) {
+    let (_, entry) = registry.entries.remove(index);
+    let subscribers = registry.subscribers.clone();
+    mem::drop(registry);
+
+    notify(&subscribers, Event::Dropped, &entry);
+  }
+}
+
+/// Modifies the detours, and notifies the subscribers of a detour's event.
+fn update<F>(key: usize, event: Event, modify: F)
+where
+  F: FnOnce(&mut Vec<(usize, DetourEntry)>),
+{
+  let mut registry = REGISTRY.lock().unwrap();
+  modify(&mut registry.entries);
+
+  let entry = registry
+    .entries
+    .iter()
+    .find(|(other, _)| *other == key)
+    .map(|(_, entry)| entry.clone());
+  let subscribers = registry.subscribers.clone();
+
+  // The callbacks may access the registry themselves
+  mem::drop(registry);
+
+  if let Some(entry) = entry {
+    notify(&subscribers, event, &entry);
+  }
+}
+
+/// Invokes each subscriber's callback.
+fn notify(subscribers: &[(usize, Arc<Callback>)], event: Event, entry: &DetourEntry) {
+  for (_, callback) in subscribers {
+    callback(event, entry);
+  }
+}
@@ -0,0 +1,130 @@
+//! Locating code by byte signatures.
+//!
+//! Functions without any symbols can be located by a pattern of their bytes,
+//! using an IDA-style syntax where each byte is written in hexadecimal, and
+//! unknown bytes are written as `?` or `??`:
+//!
+//! ```rust
+//! # use detour::Result;
+//! use detour::signature::Signature;
+//!
+//! # fn main() -> Result<()> {
+//! let code = [0x55, 0x48, 0x8B, 0xEC, 0xE8, 0x10, 0x00, 0x00, 0x00, 0xC3];
+//! let signature = Signature::new("48 8B ?? E8 ? ? ? ?")?;
+//!
+//! let address = unsafe { signature.scan(&code)? };
+//! assert_eq!(address, code[1..].as_ptr() as *const ());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A signature must have exactly one match, otherwise an error is returned.
+//! When a function is easier to identify by one of its callers, the result
+//! can instead be resolved through the relative operand of a matched
+//! instruction (e.g a `call` to the function), using `Signature::follow`.
+use crate::arch;
+use crate::error::{Error, Result};
+use std::slice;
+
+/// A byte pattern, where `None` matches any byte.
+#[derive(Debug, Clone)]
+pub struct Signature {
+  pattern: Vec<Option<u8>>,
+  follow: Option<usize>,
+}
+
+impl Signature {
+  /// Parses a signature from an IDA-style pattern (e.g `48 8B ?? E8`).
+  pub fn new(pattern: &str) -> Result<Self> {
+    let pattern = pattern
+      .split_whitespace()
+      .map(|byte| match byte {
+        "?" | "??" => Ok(None),
+        _ if byte.len() == 2 => u8::from_str_radix(byte, 16)
+          .map(Some)
+          .map_err(|_| Error::InvalidPattern),
+        _ => Err(Error::InvalidPattern),
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    if pattern.iter().all(Option::is_none) {
+      Err(Error::InvalidPattern)?;
+    }
+
+    Ok(Signature {
+      pattern,
+      follow: None,
+    })
+  }
+
+  /// Resolves matches through the relative operand of the instruction at an
+  /// offset within the pattern (e.g to the destination of a `call`).
+  pub fn follow(mut self, offset: usize) -> Self {
+    self.follow = Some(offset);
+    self
+  }
+
+  /// Scans memory for the signature's only match.
+  pub unsafe fn scan(&self, memory: &[u8]) -> Result<*const ()> {
+    self.resolve(self.matches(memory).collect())
+  }
+
+  /// Scans the executable segments of a loaded module for the signature's
+  /// only match.
+  ///
+  /// The module is referred to by its path or file name, and an empty name
+  /// refers to the main executable.
+  #[cfg(target_os = "linux")]
+  pub unsafe fn scan_module(&self, module: &str) -> Result<*const ()> {
+    let module = crate::elf::module(module)?;
+    let mut matches = Vec::new();
+
+    for segment in module
+      .segments()
+      .iter()
+      .filter(|segment| !segment.is_empty())
+    {
+      let regions = region::query_range(segment.start as *const u8, segment.end - segment.start)?;
+
+      for region in regions
+        .iter()
+        .filter(|region| region.protection.contains(region::Protection::READ_EXECUTE))
+      {
+        let start = region.lower().max(segment.start);
+        let end = region.upper().min(segment.end);
+
+        let memory = slice::from_raw_parts(start as *const u8, end - start);
+        matches.extend(self.matches(memory));
+      }
+    }
+
+    self.resolve(matches)
+  }
+
+  /// Returns the address of each match within memory.
+  fn matches<'a>(&'a self, memory: &'a [u8]) -> impl Iterator<Item = *const ()> + 'a {
+    memory
+      .windows(self.pattern.len())
+      .filter(move |window| {
+        window
+          .iter()
+          .zip(&self.pattern)
+          .all(|(byte, pattern)| pattern.map_or(true, |pattern| pattern == *byte))
+      })
+      .map(|window| window.as_ptr() as *const ())
+  }
+
+  /// Returns the address referred to by the only match.
+  unsafe fn resolve(&self, matches: Vec<*const ()>) -> Result<*const ()> {
+    let address = match matches.as_slice() {
+      [] => Err(Error::PatternNotFound)?,
+      [address] => *address,
+      _ => Err(Error::AmbiguousPattern)?,
+    };
+
+    match self.follow {
+      Some(offset) => arch::relative_operand((address as usize + offset) as *const ()),
+      None => Ok(address),
+    }
+  }
+}
@@ -0,0 +1,783 @@
+//! Thread coordination on Linux.
+//!
+//! Linux lacks an API for suspending threads, so a signal based rendezvous is
+//! used instead. Each thread listed in `/proc/self/task` is sent a signal, and
+//! its handler publishes the thread's context, and then waits until it is
+//! allowed to resume. Since the suspended threads may hold any lock (including
+//! the allocator's), no memory is allocated whilst threads are suspended.
+//! Threads which block the signal (e.g dedicated signal handling threads) are
+//! left running, as they would otherwise stall every suspension.
+//!
+//! Breakpoints are handled by a temporary `SIGTRAP` handler, and processors
+//! are serialized using `membarrier(2)`.
+use crate::error::{Error, Result};
+use lazy_static::lazy_static;
+use std::sync::atomic::{self, AtomicI32, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::{mem, ptr};
+
+/// The longest duration to wait for a thread to acknowledge a suspension.
+const SUSPEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The index of the instruction pointer within `mcontext_t::gregs`.
+#[cfg(target_arch = "x86_64")]
+const REG_IP: usize = 16;
+#[cfg(target_arch = "x86")]
+const REG_IP: usize = 14;
+
+/// Serializes the instruction stream of all threads (since Linux 4.16).
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE: libc::c_int = 1 << 5;
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE: libc::c_int = 1 << 6;
+
+/// Interrupts all threads, which implies serialization on x86 (since 4.14).
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED: libc::c_int = 1 << 3;
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: libc::c_int = 1 << 4;
+
+/// The slot's thread has been sent a suspension signal.
+const SIGNALED: u8 = 1;
+/// The slot's thread is waiting within the signal handler.
+const SUSPENDED: u8 = 2;
+/// The slot's thread exited before it could be suspended.
+const EXITED: u8 = 3;
+/// The slot's thread blocks the signal, so it's left running.
+const BLOCKED: u8 = 4;
+/// The slot's thread blocked the signal after being sent it, so the signal
+/// remains pending.
+const PENDING: u8 = 5;
+
+lazy_static! {
+  /// Serializes suspensions, since the signal handler is process-wide.
+  static ref LOCK: Mutex<()> = Mutex::new(());
+
+  /// Serializes breakpoints, since the signal handler is process-wide.
+  static ref BREAKPOINT_LOCK: Mutex<()> = Mutex::new(());
+
+  /// The action replaced by a suspension handler which has been kept, since
+  /// its signal may still be pending.
+  static ref KEPT_ACTION: Mutex<Option<libc::sigaction>> = Mutex::new(None);
+
+  /// The supported `membarrier` command used for serializing processors.
+  static ref MEMBARRIER: Option<libc::c_int> = unsafe {
+    [
+      (MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE, MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE),
+      (MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED, MEMBARRIER_CMD_PRIVATE_EXPEDITED),
+    ]
+    .iter()
+    .find(|(register, _)| libc::syscall(libc::SYS_membarrier, *register, 0) == 0)
+    .map(|(_, command)| *command)
+  };
+}
+
+/// The state of the active suspension, shared with the signal handler.
+static STATE: AtomicPtr<State> = AtomicPtr::new(ptr::null_mut());
+
+/// The number of signal handlers currently executing.
+static HANDLERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of breakpoint handlers currently inspecting the breakpoint.
+static TRAPS: AtomicUsize = AtomicUsize::new(0);
+
+/// The address of the armed breakpoint's `int3` instruction.
+static BREAKPOINT_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// The address where threads hitting the armed breakpoint are redirected.
+static BREAKPOINT_REDIRECT: AtomicUsize = AtomicUsize::new(0);
+
+/// The `SIGTRAP` action replaced by the breakpoint handler.
+static PREVIOUS_TRAP_ACTION: AtomicPtr<libc::sigaction> = AtomicPtr::new(ptr::null_mut());
+
+/// A thread that is (or is about to be) suspended.
+#[derive(Default)]
+struct Slot {
+  tid: AtomicI32,
+  status: AtomicU8,
+  context: AtomicPtr<libc::ucontext_t>,
+}
+
+/// Data shared between the suspending thread and the signal handlers.
+struct State {
+  slots: Box<[Slot]>,
+  len: AtomicUsize,
+  resume: AtomicI32,
+}
+
+impl State {
+  /// Returns all slots that have been assigned a thread.
+  fn slots(&self) -> &[Slot] {
+    &self.slots[..self.len.load(Ordering::SeqCst)]
+  }
+}
+
+/// Suspends all other threads of the process until dropped.
+pub struct Freeze {
+  state: Box<State>,
+  previous: libc::sigaction,
+  _guard: MutexGuard<'static, ()>,
+}
+
+impl Freeze {
+  /// Suspends all threads of the process, except the current one and any
+  /// which block the suspension signal.
+  pub unsafe fn new() -> Result<Self> {
+    let guard = LOCK.lock().unwrap();
+
+    // Reserve slots up front, since nothing may be allocated later on. New
+    // threads may be spawned during the suspension, so leave a margin.
+    let mut threads = 0;
+    for_each_task(|_| {
+      threads += 1;
+      Ok(())
+    })?;
+
+    let state = Box::new(State {
+      slots: (0..threads * 2 + 32).map(|_| Slot::default()).collect(),
+      len: AtomicUsize::new(0),
+      resume: AtomicI32::new(0),
+    });
+
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handle_suspend as *const () as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    libc::sigfillset(&mut action.sa_mask);
+
+    let mut previous: libc::sigaction = mem::zeroed();
+    if libc::sigaction(signal(), &action, &mut previous) != 0 {
+      Err(Error::SuspendFailure)?;
+    }
+
+    // The handler kept by an earlier suspension is not the original action
+    if let Some(original) = KEPT_ACTION.lock().unwrap().take() {
+      previous = original;
+    }
+
+    STATE.store(&*state as *const _ as *mut _, Ordering::SeqCst);
+    let freeze = Freeze {
+      _guard: guard,
+      previous,
+      state,
+    };
+
+    // In case of failure, any suspended threads are resumed when dropped
+    freeze.suspend()?;
+    Ok(freeze)
+  }
+
+  /// Moves the instruction pointer of each suspended thread, if `relocate`
+  /// returns a new address for it.
+  pub unsafe fn relocate<F: Fn(usize) -> Option<usize>>(&self, relocate: F) {
+    for slot in self.state.slots() {
+      if slot.status.load(Ordering::SeqCst) != SUSPENDED {
+        continue;
+      }
+
+      let ip = instruction_pointer(slot.context.load(Ordering::SeqCst));
+
+      if let Some(address) = relocate(*ip) {
+        *ip = address;
+      }
+    }
+  }
+
+  /// Returns whether the instruction pointer of any suspended thread
+  /// satisfies a predicate.
+  pub unsafe fn any<F: Fn(usize) -> bool>(&self, predicate: F) -> bool {
+    self.state.slots().iter().any(|slot| {
+      slot.status.load(Ordering::SeqCst) == SUSPENDED
+        && predicate(*instruction_pointer(slot.context.load(Ordering::SeqCst)))
+    })
+  }
+
+  /// Signals all threads until every one of them is suspended.
+  unsafe fn suspend(&self) -> Result<()> {
+    let pid = libc::getpid();
+    let current = gettid();
+
+    // Threads may spawn others before they are suspended, so the tasks are
+    // enumerated until no unknown thread remains.
+    loop {
+      let mut signaled = false;
+
+      for_each_task(|tid| {
+        let slots = self.state.slots();
+        if tid == current
+          || slots
+            .iter()
+            .any(|slot| slot.tid.load(Ordering::SeqCst) == tid)
+        {
+          return Ok(());
+        }
+
+        let slot = self
+          .state
+          .slots
+          .get(slots.len())
+          .ok_or(Error::SuspendFailure)?;
+        slot.tid.store(tid, Ordering::SeqCst);
+        slot.status.store(SIGNALED, Ordering::SeqCst);
+        self.state.len.store(slots.len() + 1, Ordering::SeqCst);
+
+        if is_signal_blocked(tid) {
+          slot.status.store(BLOCKED, Ordering::SeqCst);
+        } else if tgkill(pid, tid, signal()) == 0 {
+          signaled = true;
+        } else if errno() == libc::ESRCH {
+          slot.status.store(EXITED, Ordering::SeqCst);
+        } else {
+          Err(Error::SuspendFailure)?;
+        }
+        Ok(())
+      })?;
+
+      if !signaled {
+        return Ok(());
+      }
+
+      self.wait()?;
+    }
+  }
+
+  /// Waits until all signaled threads have acknowledged their suspension.
+  unsafe fn wait(&self) -> Result<()> {
+    let pid = libc::getpid();
+    let start = Instant::now();
+
+    loop {
+      let mut pending = false;
+
+      for slot in self.state.slots() {
+        if slot.status.load(Ordering::SeqCst) != SIGNALED {
+          continue;
+        }
+
+        // A thread may exit, or block the signal, before it handles the signal
+        let tid = slot.tid.load(Ordering::SeqCst);
+        if tgkill(pid, tid, 0) != 0 && errno() == libc::ESRCH {
+          let _ =
+            slot
+              .status
+              .compare_exchange(SIGNALED, EXITED, Ordering::SeqCst, Ordering::SeqCst);
+        } else if is_signal_blocked(tid) {
+          let _ =
+            slot
+              .status
+              .compare_exchange(SIGNALED, PENDING, Ordering::SeqCst, Ordering::SeqCst);
+        } else {
+          pending = true;
+        }
+      }
+
+      if !pending {
+        return Ok(());
+      }
+
+      if start.elapsed() > SUSPEND_TIMEOUT {
+        Err(Error::SuspendFailure)?;
+      }
+
+      libc::sched_yield();
+    }
+  }
+}
+
+impl Drop for Freeze {
+  /// Resumes all suspended threads.
+  fn drop(&mut self) {
+    self.state.resume.store(1, Ordering::SeqCst);
+    STATE.store(ptr::null_mut(), Ordering::SeqCst);
+    unsafe {
+      futex(
+        &self.state.resume,
+        libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+        libc::c_int::MAX,
+      )
+    };
+
+    // The state must outlive all handlers that may be referencing it
+    while HANDLERS.load(Ordering::SeqCst) > 0 {
+      unsafe { libc::sched_yield() };
+    }
+
+    // If a signaled thread never handled its signal, it may still be pending.
+    // In that case the handler is kept, since the default action is
+    // termination. Threads which were never signaled are irrelevant.
+    let is_signal_pending = self.state.slots().iter().any(|slot| {
+      let status = slot.status.load(Ordering::SeqCst);
+      status == SIGNALED || status == PENDING
+    });
+
+    if is_signal_pending {
+      *KEPT_ACTION.lock().unwrap() = Some(self.previous);
+    } else {
+      unsafe { libc::sigaction(signal(), &self.previous, ptr::null_mut()) };
+    }
+  }
+}
+
+/// A handler redirecting threads that execute an `int3` at an armed address,
+/// installed until dropped.
+///
+/// It must be created before any threads are suspended, since installing it
+/// allocates memory.
+pub struct Breakpoint {
+  previous: Box<libc::sigaction>,
+  _guard: MutexGuard<'static, ()>,
+}
+
+impl Breakpoint {
+  /// Installs the `SIGTRAP` handler, without any address being armed.
+  ///
+  /// Returns `None` if the handler cannot be installed.
+  pub unsafe fn new() -> Option<Self> {
+    let guard = BREAKPOINT_LOCK.lock().unwrap();
+
+    let mut previous: Box<libc::sigaction> = Box::new(mem::zeroed());
+    if libc::sigaction(libc::SIGTRAP, ptr::null(), &mut *previous) != 0 {
+      return None;
+    }
+
+    PREVIOUS_TRAP_ACTION.store(&mut *previous, Ordering::SeqCst);
+
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handle_breakpoint as *const () as usize;
+    action.sa_flags = libc::SA_SIGINFO;
+
+    if libc::sigaction(libc::SIGTRAP, &action, ptr::null_mut()) != 0 {
+      PREVIOUS_TRAP_ACTION.store(ptr::null_mut(), Ordering::SeqCst);
+      return None;
+    }
+
+    Some(Breakpoint {
+      _guard: guard,
+      previous,
+    })
+  }
+
+  /// Redirects any thread that executes an `int3` at `address` to `redirect`.
+  /// The `int3` itself is placed by the caller.
+  pub fn arm(&self, address: *const u8, redirect: *const ()) {
+    BREAKPOINT_REDIRECT.store(redirect as usize, Ordering::SeqCst);
+    BREAKPOINT_ADDRESS.store(address as usize, Ordering::SeqCst);
+  }
+
+  /// Stops redirecting threads, once the `int3` has been removed.
+  pub unsafe fn disarm(&self) {
+    // Ensure that no thread can observe the breakpoint any longer, and that
+    // any thread which already has, is done redirecting itself.
+    serialize();
+    wait_for_traps();
+    BREAKPOINT_ADDRESS.store(0, Ordering::SeqCst);
+  }
+}
+
+impl Drop for Breakpoint {
+  /// Restores the previous `SIGTRAP` action.
+  fn drop(&mut self) {
+    unsafe {
+      self.disarm();
+      libc::sigaction(libc::SIGTRAP, &*self.previous, ptr::null_mut());
+    }
+
+    // The previous action must outlive all handlers that may be reading it
+    PREVIOUS_TRAP_ACTION.store(ptr::null_mut(), Ordering::SeqCst);
+    wait_for_traps();
+  }
+}
+
+/// Waits until no breakpoint handler is inspecting the breakpoint.
+fn wait_for_traps() {
+  while TRAPS.load(Ordering::SeqCst) > 0 {
+    unsafe { libc::sched_yield() };
+  }
+}
+
+/// Ensures that no processor executing any of the process's threads uses
+/// stale (e.g prefetched) instructions after code has been modified.
+pub unsafe fn serialize() {
+  atomic::fence(Ordering::SeqCst);
+
+  if let Some(command) = *MEMBARRIER {
+    libc::syscall(libc::SYS_membarrier, command, 0);
+  }
+}
+
+/// Redirects threads hitting the armed breakpoint, and forwards any other
+/// trap to the previous handler.
+unsafe extern "C" fn handle_breakpoint(
+  signal: libc::c_int,
+  info: *mut libc::siginfo_t,
+  context: *mut libc::c_void,
+) {
+  // The breakpoint is only released once the handler is done with it. Any
+  // other handler may not return, so it's invoked afterwards.
+  TRAPS.fetch_add(1, Ordering::SeqCst);
+
+  // The instruction pointer is placed after the `int3` instruction
+  let ip = instruction_pointer(context as *mut _);
+  let address = BREAKPOINT_ADDRESS.load(Ordering::SeqCst);
+  let is_redirected = address != 0 && (*ip).wrapping_sub(1) == address;
+
+  if is_redirected {
+    *ip = BREAKPOINT_REDIRECT.load(Ordering::SeqCst);
+  }
+
+  let previous = PREVIOUS_TRAP_ACTION
+    .load(Ordering::SeqCst)
+    .as_ref()
+    .map(|action| (action.sa_sigaction, action.sa_flags));
+  TRAPS.fetch_sub(1, Ordering::SeqCst);
+
+  if is_redirected {
+    return;
+  }
+
+  match previous {
+    Some((handler, _)) if handler == libc::SIG_IGN => (),
+    Some((handler, flags)) if handler != libc::SIG_DFL => {
+      if flags & libc::SA_SIGINFO != 0 {
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+          mem::transmute(handler);
+        handler(signal, info, context);
+      } else {
+        let handler: extern "C" fn(libc::c_int) = mem::transmute(handler);
+        handler(signal);
+      }
+    },
+    _ => {
+      // Let the default action take place once the handler returns
+      libc::signal(libc::SIGTRAP, libc::SIG_DFL);
+      libc::raise(libc::SIGTRAP);
+    },
+  }
+}
+
+/// Suspends the current thread, until the active suspension is over.
+unsafe extern "C" fn handle_suspend(
+  _signal: libc::c_int,
+  _info: *mut libc::siginfo_t,
+  context: *mut libc::c_void,
+) {
+  HANDLERS.fetch_add(1, Ordering::SeqCst);
+  let saved_errno = errno();
+
+  if let Some(state) = STATE.load(Ordering::SeqCst).as_ref() {
+    let tid = gettid();
+
+    if let Some(slot) = state
+      .slots()
+      .iter()
+      .find(|slot| slot.tid.load(Ordering::SeqCst) == tid)
+    {
+      slot.context.store(context as *mut _, Ordering::SeqCst);
+
+      let is_suspended = slot
+        .status
+        .compare_exchange(SIGNALED, SUSPENDED, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok();
+
+      if is_suspended {
+        while state.resume.load(Ordering::SeqCst) == 0 {
+          futex(
+            &state.resume,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            0,
+          );
+        }
+      }
+    }
+  }
+
+  *libc::__errno_location() = saved_errno;
+  HANDLERS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Invokes a closure for each thread ID of the process.
+///
+/// The directory is read using raw system calls, so nothing is allocated.
+unsafe fn for_each_task<F: FnMut(libc::pid_t) -> Result<()>>(mut callback: F) -> Result<()> {
+  /// An owned file descriptor.
+  struct Descriptor(libc::c_int);
+
+  impl Drop for Descriptor {
+    fn drop(&mut self) {
+      unsafe { libc::close(self.0) };
+    }
+  }
+
+  let path = b"/proc/self/task\0";
+  let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+  let descriptor = match libc::open(path.as_ptr() as *const _, flags) {
+    -1 => Err(Error::SuspendFailure)?,
+    fd => Descriptor(fd),
+  };
+
+  // Use an aligned buffer, since it's interpreted as `linux_dirent64`
+  let mut buffer = [0u64; 256];
+
+  loop {
+    let length = libc::syscall(
+      libc::SYS_getdents64,
+      descriptor.0,
+      buffer.as_mut_ptr(),
+      mem::size_of_val(&buffer),
+    );
+
+    match length {
+      0 => return Ok(()),
+      length if length < 0 => Err(Error::SuspendFailure)?,
+      _ => (),
+    }
+
+    let mut offset = 0;
+    while offset < length as usize {
+      // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen; u8 d_type; char
+      // d_name[]; }
+      let entry = (buffer.as_ptr() as *const u8).add(offset);
+      let record_length = ptr::read_unaligned(entry.add(16) as *const u16) as usize;
+      let name = entry.add(19);
+
+      let mut tid: libc::pid_t = 0;
+      let mut index = 0;
+      while *name.add(index) != 0 {
+        match *name.add(index) {
+          digit @ b'0'..=b'9' => tid = tid * 10 + libc::pid_t::from(digit - b'0'),
+          _ => {
+            // Skip any '.' and '..' entries
+            tid = 0;
+            break;
+          },
+        }
+        index += 1;
+      }
+
+      if tid > 0 {
+        callback(tid)?;
+      }
+
+      offset += record_length;
+    }
+  }
+}
+
+/// Returns whether a thread blocks the suspension signal, according to the
+/// `SigBlk` mask of `/proc/self/task/<tid>/status`.
+///
+/// The file is read using raw system calls, so nothing is allocated.
+unsafe fn is_signal_blocked(tid: libc::pid_t) -> bool {
+  const PREFIX: &[u8] = b"/proc/self/task/";
+  const SUFFIX: &[u8] = b"/status\0";
+  const FIELD: &[u8] = b"\nSigBlk:\t";
+
+  let mut path = [0u8; 64];
+  path[..PREFIX.len()].copy_from_slice(PREFIX);
+  let mut length = PREFIX.len();
+
+  let mut digits = [0u8; 10];
+  let mut count = 0;
+  let mut remainder = tid;
+  loop {
+    digits[count] = b'0' + (remainder % 10) as u8;
+    remainder /= 10;
+    count += 1;
+    if remainder == 0 {
+      break;
+    }
+  }
+  for digit in digits[..count].iter().rev() {
+    path[length] = *digit;
+    length += 1;
+  }
+  path[length..length + SUFFIX.len()].copy_from_slice(SUFFIX);
+
+  let descriptor = libc::open(path.as_ptr() as *const _, libc::O_RDONLY | libc::O_CLOEXEC);
+  if descriptor == -1 {
+    return false;
+  }
+
+  let mut buffer = [0u8; 4096];
+  let mut length = 0;
+  while length < buffer.len() {
+    match libc::read(
+      descriptor,
+      buffer[length..].as_mut_ptr() as *mut _,
+      buffer.len() - length,
+    ) {
+      read if read > 0 => length += read as usize,
+      _ => break,
+    }
+  }
+  libc::close(descriptor);
+
+  // The mask is printed as hexadecimal digits, with the first signal as the
+  // least significant bit.
+  let status = &buffer[..length];
+  let mask = match status
+    .windows(FIELD.len())
+    .position(|window| window == FIELD)
+  {
+    Some(index) => &status[index + FIELD.len()..],
+    None => return false,
+  };
+
+  let digits = mask
+    .iter()
+    .take_while(|digit| digit.is_ascii_hexdigit())
+    .count();
+  let bit = (signal() - 1) as usize;
+  if bit / 4 >= digits {
+    return false;
+  }
+
+  let digit = match mask[digits - 1 - bit / 4] {
+    digit @ b'0'..=b'9' => digit - b'0',
+    digit => (digit | 0x20) - b'a' + 10,
+  };
+  digit & (1 << (bit % 4)) != 0
+}
+
+/// Returns a pointer to the instruction pointer of a signal context.
+unsafe fn instruction_pointer(context: *mut libc::ucontext_t) -> *mut usize {
+  (&mut (*context).uc_mcontext as *mut _ as *mut usize).add(REG_IP)
+}
+
+/// Returns the signal used for suspending threads.
+fn signal() -> libc::c_int {
+  libc::SIGRTMAX() - 2
+}
+
+/// Returns the current thread's ID.
+unsafe fn gettid() -> libc::pid_t {
+  libc::syscall(libc::SYS_gettid) as libc::pid_t
+}
+
+/// Sends a signal to a specific thread.
+unsafe fn tgkill(pid: libc::pid_t, tid: libc::pid_t, signal: libc::c_int) -> libc::c_long {
+  libc::syscall(libc::SYS_tgkill, pid, tid, signal)
+}
+
+/// Waits on, or wakes threads waiting on, a futex.
+unsafe fn futex(address: &AtomicI32, operation: libc::c_int, value: libc::c_int) {
+  let timeout: *const libc::timespec = ptr::null();
+  libc::syscall(
+    libc::SYS_futex,
+    address as *const AtomicI32,
+    operation,
+    value,
+    timeout,
+  );
+}
+
+/// Returns the current thread's last error.
+unsafe fn errno() -> libc::c_int {
+  *libc::__errno_location()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::arch::memory;
+  use crate::RawDetour;
+  use std::sync::mpsc;
+  use std::thread;
+  use std::time::Instant;
+
+  #[test]
+  fn detour_toggled_with_blocked_signals() -> Result<()> {
+    #[inline(never)]
+    extern "C" fn mul(x: i32, y: i32) -> i32 {
+      unsafe { ptr::read_volatile(&x as *const i32) * y }
+    }
+
+    extern "C" fn div(x: i32, y: i32) -> i32 {
+      x / y
+    }
+
+    extern "C" fn ignore(_: libc::c_int) {}
+
+    let hook = unsafe { RawDetour::new(mul as *const (), div as *const ())? };
+    let (blocked, is_blocked) = mpsc::channel();
+    let (done, is_done) = mpsc::channel::<()>();
+
+    // A thread blocking every signal cannot be suspended
+    let worker = thread::spawn(move || unsafe {
+      let mut set = mem::zeroed();
+      libc::sigfillset(&mut set);
+      libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+      blocked.send(()).unwrap();
+      is_done.recv().unwrap();
+    });
+    is_blocked.recv().unwrap();
+
+    // Replace the action whilst no other suspension is active
+    let mut original: libc::sigaction = unsafe { mem::zeroed() };
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = ignore as *const () as usize;
+    unsafe {
+      let _guard = LOCK.lock().unwrap();
+      libc::sigaction(signal(), &action, &mut original);
+    }
+
+    let start = Instant::now();
+    unsafe { hook.enable()? };
+    assert_eq!(mul(10, 5), 2);
+    unsafe { hook.disable()? };
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    // The thread was never signaled, so the previous action is restored
+    unsafe {
+      let _guard = LOCK.lock().unwrap();
+      let mut current: libc::sigaction = mem::zeroed();
+      libc::sigaction(signal(), &original, &mut current);
+      assert_eq!(current.sa_sigaction, action.sa_sigaction);
+    }
+
+    done.send(()).unwrap();
+    worker.join().unwrap();
+    Ok(())
+  }
+
+  #[test]
+  fn breakpoint_redirect() -> Result<()> {
+    extern "C" fn ret10() -> i32 {
+      10
+    }
+
+    // int3; mov eax, 1; ret
+    let code = [0xCC, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xC3];
+    let mut memory = memory::POOL
+      .lock()
+      .unwrap()
+      .allocate(ret10 as *const (), code.len())?;
+    memory.copy_from_slice(&code);
+
+    unsafe {
+      let target: unsafe extern "C" fn() -> i32 = mem::transmute(memory.as_ptr());
+      let breakpoint = Breakpoint::new().expect("breakpoint handler");
+
+      breakpoint.arm(memory.as_ptr(), ret10 as *const ());
+      assert_eq!(target(), 10);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn breakpoint_disarm_waits_for_traps() {
+    let breakpoint = unsafe { Breakpoint::new() }.expect("breakpoint handler");
+    let address = breakpoint_disarm_waits_for_traps as *const u8;
+    breakpoint.arm(address, ptr::null());
+
+    // A handler which has yet to redirect its thread
+    TRAPS.fetch_add(1, Ordering::SeqCst);
+
+    thread::scope(|scope| {
+      let disarm = scope.spawn(|| unsafe { breakpoint.disarm() });
+      thread::sleep(Duration::from_millis(50));
+      assert_eq!(BREAKPOINT_ADDRESS.load(Ordering::SeqCst), address as usize);
+
+      TRAPS.fetch_sub(1, Ordering::SeqCst);
+      disarm.join().unwrap();
+    });
+
+    assert_eq!(BREAKPOINT_ADDRESS.load(Ordering::SeqCst), 0);
+  }
+}
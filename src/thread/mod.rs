@@ -0,0 +1,58 @@
+//! Coordination with the process's threads whilst code is being modified.
+//!
+//! Other threads may be executing the very instructions that are about to be
+//! replaced. A `Freeze` suspends every thread except the current one, and
+//! allows their instruction pointers to be moved before they are resumed. A
+//! `Breakpoint` redirects threads that execute an `int3` placed whilst code is
+//! being written, and `serialize` ensures that no processor executes stale
+//! instructions.
+use cfg_if::cfg_if;
+
+cfg_if! {
+  if #[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))] {
+    mod linux;
+    pub use self::linux::{serialize, Breakpoint, Freeze};
+  } else {
+    use crate::error::Result;
+    use std::sync::atomic::{fence, Ordering};
+
+    /// A no-op freeze, used on platforms without thread suspension.
+    pub struct Freeze;
+
+    impl Freeze {
+      /// Pretends to suspend all other threads of the process.
+      pub unsafe fn new() -> Result<Self> {
+        Ok(Freeze)
+      }
+
+      /// Does nothing, since there are no suspended threads.
+      pub unsafe fn relocate<F: Fn(usize) -> Option<usize>>(&self, _relocate: F) {}
+
+      /// Returns false, since there are no suspended threads.
+      pub unsafe fn any<F: Fn(usize) -> bool>(&self, _predicate: F) -> bool {
+        false
+      }
+    }
+
+    /// A placeholder, used on platforms without breakpoint handling.
+    pub struct Breakpoint;
+
+    impl Breakpoint {
+      /// Returns `None`, since breakpoints cannot be handled.
+      pub unsafe fn new() -> Option<Self> {
+        None
+      }
+
+      /// Does nothing, since the handler is never installed.
+      pub fn arm(&self, _address: *const u8, _redirect: *const ()) {}
+
+      /// Does nothing, since the handler is never installed.
+      pub unsafe fn disarm(&self) {}
+    }
+
+    /// Orders the preceding code modifications.
+    pub unsafe fn serialize() {
+      fence(Ordering::SeqCst);
+    }
+  }
+}
@@ -0,0 +1,76 @@
+//! Fixed-size sets of keys, for use within thread-local storage.
+//!
+//! They never allocate, so they can be used by detours of functions such as
+//! `malloc` without any recursion.
+use std::cell::Cell;
+
+/// The maximum amount of keys in a set.
+const CAPACITY: usize = 32;
+
+/// A set of keys, which may contain duplicates.
+pub struct Keys {
+  keys: Cell<[usize; CAPACITY]>,
+  len: Cell<usize>,
+}
+
+impl Keys {
+  /// Creates an empty set.
+  pub const fn new() -> Self {
+    Keys {
+      keys: Cell::new([0; CAPACITY]),
+      len: Cell::new(0),
+    }
+  }
+
+  /// Adds a key, returning whether there was room for it.
+  pub fn insert(&self, key: usize) -> bool {
+    let len = self.len.get();
+    if len == CAPACITY {
+      return false;
+    }
+
+    let mut keys = self.keys.get();
+    keys[len] = key;
+    self.keys.set(keys);
+    self.len.set(len + 1);
+    true
+  }
+
+  /// Removes the last added occurrence of a key.
+  pub fn remove(&self, key: usize) {
+    let mut keys = self.keys.get();
+    let len = self.len.get();
+
+    if let Some(index) = keys[..len].iter().rposition(|other| *other == key) {
+      keys.copy_within(index + 1..len, index);
+      self.keys.set(keys);
+      self.len.set(len - 1);
+    }
+  }
+
+  /// Returns whether the set contains a key.
+  pub fn contains(&self, key: usize) -> bool {
+    self.keys.get()[..self.len.get()].contains(&key)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keys_beyond_capacity() {
+    let keys = Keys::new();
+    assert!((0..CAPACITY).all(|_| keys.insert(1)));
+    assert!(!keys.insert(2));
+    assert!(!keys.contains(2));
+
+    // Only the keys which were inserted are removed
+    keys.remove(2);
+    for _ in 0..CAPACITY {
+      assert!(keys.contains(1));
+      keys.remove(1);
+    }
+    assert!(!keys.contains(1));
+  }
+}
@@ -12,6 +12,15 @@ pub unsafe trait Function: Sized + Copy + Sync + 'static {
   /// The return type.
   type Output;
 
+  /// The closure type used as a static detour for this function.
+  ///
+  /// It may be invoked by several threads at once, so it's both `Send` and
+  /// `Sync`.
+  type Closure: ?Sized + Send + Sync;
+
+  /// Whether a panic may unwind out of this function.
+  const UNWINDS: bool = false;
+
   /// Constructs a `Function` from an untyped pointer.
   unsafe fn from_ptr(ptr: *const ()) -> Self;
 
@@ -24,7 +33,26 @@ pub unsafe trait HookableWith<D: Function>: Function {}
 
 unsafe impl<T: Function> HookableWith<T> for T {}
 
+/// Trait implemented by all detour types.
+///
+/// This allows detours of different types to be handled uniformly, e.g.
+/// within a [DetourTransaction](./struct.DetourTransaction.html). It is sealed
+/// and cannot be implemented outside of this library.
+pub trait AnyDetour: private::Sealed {}
+
+impl<T: private::Sealed> AnyDetour for T {}
+
+pub(crate) mod private {
+  /// Provides access to the underlying detour.
+  pub trait Sealed {
+    /// Returns the architecture-independent detour.
+    fn base(&self) -> crate::Result<&crate::arch::Detour>;
+  }
+}
+
 impl_hookable! {
-  __arg_0:  A, __arg_1:  B, __arg_2:  C, __arg_3:  D, __arg_4:  E, __arg_5:  F, __arg_6:  G,
-  __arg_7:  H, __arg_8:  I, __arg_9:  J, __arg_10: K, __arg_11: L, __arg_12: M, __arg_13: N
+  __arg_0:  A, __arg_1:  B, __arg_2:  C, __arg_3:  D, __arg_4:  E, __arg_5:  F,
+  __arg_6:  G, __arg_7:  H, __arg_8:  I, __arg_9:  J, __arg_10: K, __arg_11: L,
+  __arg_12: M, __arg_13: N, __arg_14: O, __arg_15: P, __arg_16: Q, __arg_17: R,
+  __arg_18: S, __arg_19: T, __arg_20: U, __arg_21: V, __arg_22: W, __arg_23: X
 }
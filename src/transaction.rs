@@ -0,0 +1,93 @@
+use crate::arch::Detour;
+use crate::error::Result;
+use crate::traits::AnyDetour;
+
+/// A set of detour operations that are applied atomically.
+///
+/// Any mix of detour types can be enabled or disabled within the same
+/// transaction. When committed, all operations are applied whilst holding a
+/// single lock, and each affected page only has its protection changed once.
+/// Everything that may fail (e.g changing the protection of pages, or
+/// suspending threads) is done before any code is modified, so the detours are
+/// never left partially toggled.
+///
+/// # Example
+///
+/// ```rust
+/// # use detour::Result;
+/// use detour::{DetourTransaction, GenericDetour, RawDetour};
+///
+/// fn add5(val: i32) -> i32 {
+///   val + 5
+/// }
+///
+/// fn add10(val: i32) -> i32 {
+///   val + 10
+/// }
+///
+/// fn sub5(val: i32) -> i32 {
+///   val - 5
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let hook1 = unsafe { GenericDetour::<fn(i32) -> i32>::new(add5, add10)? };
+/// let hook2 = unsafe { RawDetour::new(add10 as *const (), sub5 as *const ())? };
+///
+/// let mut transaction = DetourTransaction::new();
+/// transaction.enable(&hook1).enable(&hook2);
+/// unsafe { transaction.commit()? };
+///
+/// assert!(hook1.is_enabled() && hook2.is_enabled());
+/// assert_eq!(add10(5), 0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct DetourTransaction<'a> {
+  operations: Vec<(&'a dyn AnyDetour, bool)>,
+}
+
+impl<'a> DetourTransaction<'a> {
+  /// Creates a new, empty transaction.
+  pub fn new() -> Self {
+    DetourTransaction {
+      operations: Vec::new(),
+    }
+  }
+
+  /// Adds an operation enabling a detour.
+  pub fn enable<D: AnyDetour>(&mut self, detour: &'a D) -> &mut Self {
+    self.operations.push((detour, true));
+    self
+  }
+
+  /// Adds an operation disabling a detour.
+  pub fn disable<D: AnyDetour>(&mut self, detour: &'a D) -> &mut Self {
+    self.operations.push((detour, false));
+    self
+  }
+
+  /// Returns the number of operations in the transaction.
+  pub fn len(&self) -> usize {
+    self.operations.len()
+  }
+
+  /// Returns whether the transaction has no operations or not.
+  pub fn is_empty(&self) -> bool {
+    self.operations.is_empty()
+  }
+
+  /// Applies all operations, in the order they were added.
+  ///
+  /// If any operation cannot be applied, none of them are, and the error is
+  /// returned.
+  pub unsafe fn commit(self) -> Result<()> {
+    let operations = self
+      .operations
+      .into_iter()
+      .map(|(detour, enabled)| detour.base().map(|detour| (detour, enabled)))
+      .collect::<Result<Vec<(&Detour, bool)>>>()?;
+
+    Detour::toggle_all(&operations)
+  }
+}
@@ -1,4 +1,5 @@
 use crate::error::Result;
+use std::mem;
 
 /// Returns true if an address is executable.
 pub fn is_executable_address(address: *const ()) -> Result<bool> {
@@ -8,3 +9,15 @@ pub fn is_executable_address(address: *const ()) -> Result<bool> {
       .contains(region::Protection::EXECUTE),
   )
 }
+
+/// Makes a value writable until the returned guard is dropped.
+///
+/// Its other permissions are retained, since e.g code may share its page.
+pub unsafe fn make_writable<T>(address: *const T) -> Result<region::ProtectGuard> {
+  let protection = region::query(address as *const _)?.protection;
+  Ok(region::protect_with_handle(
+    address as *const u8,
+    mem::size_of::<T>(),
+    protection | region::Protection::WRITE,
+  )?)
+}
@@ -0,0 +1,161 @@
+//! Access to the arguments of variadic functions.
+use crate::arch::{self, memory, Arguments, Cursor};
+use crate::error::Result;
+use std::any::TypeId;
+use std::cell::Cell;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+  /// The arguments captured by the variadic thunk the current thread is
+  /// passing through.
+  static CAPTURED: Cell<Arguments> = const { Cell::new(Arguments::new()) };
+}
+
+/// The address of the code calling functions with captured arguments.
+static FORWARDER: AtomicUsize = AtomicUsize::new(0);
+
+/// The arguments of a call to a variadic function.
+///
+/// It's passed to closures detouring a variadic function (see
+/// [GenericDetour](./struct.GenericDetour.html)), after the fixed arguments.
+/// The variadic arguments can be read using a [VaList](./struct.VaList.html),
+/// or be forwarded unchanged to the original function, using `call`.
+///
+/// The registers which may contain arguments are captured, along with the
+/// first 32 words of stack arguments. Arguments must be integers, pointers or
+/// floating-point values, and the return value must not be passed in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct VariadicArgs {
+  arguments: Arguments,
+  /// The position following the fixed arguments.
+  cursor: Cursor,
+}
+
+impl VariadicArgs {
+  /// Returns a list of the variadic arguments.
+  pub fn list(&self) -> VaList<'_> {
+    VaList {
+      arguments: &self.arguments,
+      cursor: self.cursor,
+    }
+  }
+
+  /// Returns the arguments captured by the current thread's variadic thunk.
+  ///
+  /// This must only be called by a shim, before anything else.
+  pub(crate) fn current() -> Self {
+    VariadicArgs {
+      arguments: CAPTURED.with(Cell::get),
+      cursor: Cursor::default(),
+    }
+  }
+
+  /// Skips a fixed argument.
+  pub(crate) fn skip<T: 'static>(&mut self) {
+    self.cursor.next(mem::size_of::<T>(), is_float::<T>());
+  }
+
+  /// Returns a copy of the arguments, without any fixed arguments.
+  pub(crate) fn rewind(&self) -> Self {
+    VariadicArgs {
+      arguments: self.arguments,
+      cursor: Cursor::default(),
+    }
+  }
+
+  /// Replaces a fixed argument.
+  pub(crate) fn push<T: 'static>(&mut self, value: T) {
+    let offset = self.cursor.next(mem::size_of::<T>(), is_float::<T>());
+    unsafe { self.arguments.write(offset, value) };
+  }
+
+  /// Calls a function with the arguments, as they were passed by the caller.
+  pub(crate) unsafe fn forward<Ret>(&self, target: *const ()) -> Ret {
+    let forwarder = FORWARDER.load(Ordering::SeqCst);
+    assert_ne!(forwarder, 0, "variadic forwarder not allocated");
+
+    let forward: extern "C" fn(*const Arguments, *const ()) -> Ret = mem::transmute(forwarder);
+    forward(&self.arguments, target)
+  }
+}
+
+/// A list of variadic arguments, akin to C's `va_list`.
+#[derive(Debug, Clone)]
+pub struct VaList<'a> {
+  arguments: &'a Arguments,
+  cursor: Cursor,
+}
+
+impl<'a> VaList<'a> {
+  /// Returns the next argument.
+  ///
+  /// Like `va_arg`, the type must match the (promoted) type passed by the
+  /// caller, and there must be an argument remaining.
+  pub unsafe fn arg<T: VaArgument>(&mut self) -> T {
+    let offset = self.cursor.next(mem::size_of::<T>(), T::FLOAT);
+    self.arguments.read(offset)
+  }
+}
+
+/// Types which can be read from a variadic argument list.
+///
+/// It is sealed, and implemented for the types a C variadic argument is
+/// promoted to.
+pub unsafe trait VaArgument: private::Sealed + Copy {
+  #[doc(hidden)]
+  const FLOAT: bool;
+}
+
+mod private {
+  pub trait Sealed {}
+}
+
+macro_rules! impl_va_argument {
+  ($float:expr => $($ty:ty),*) => {
+    $(
+      impl private::Sealed for $ty {}
+      unsafe impl VaArgument for $ty {
+        const FLOAT: bool = $float;
+      }
+    )*
+  };
+}
+
+impl_va_argument!(false => i32, u32, i64, u64, isize, usize);
+impl_va_argument!(true => f64);
+
+impl<T> private::Sealed for *const T {}
+unsafe impl<T> VaArgument for *const T {
+  const FLOAT: bool = false;
+}
+
+impl<T> private::Sealed for *mut T {}
+unsafe impl<T> VaArgument for *mut T {
+  const FLOAT: bool = false;
+}
+
+/// Captures the arguments saved by a selector stub, for the current thread.
+pub(crate) unsafe fn capture(stack: usize) {
+  CAPTURED.with(|captured| captured.set(Arguments::capture(stack)));
+}
+
+/// Allocates the code calling functions with captured arguments, unless it
+/// already has been.
+pub(crate) fn allocate_forwarder() -> Result<()> {
+  let mut pool = memory::POOL.lock().unwrap();
+  if FORWARDER.load(Ordering::SeqCst) == 0 {
+    let emitter = arch::forwarder_builder();
+    let forwarder = memory::allocate_pic(&mut pool, &emitter, allocate_forwarder as *const ())?;
+    FORWARDER.store(forwarder.as_ptr() as usize, Ordering::SeqCst);
+
+    // The forwarder is shared by all detours, and never released
+    mem::forget(forwarder);
+  }
+  Ok(())
+}
+
+/// Returns whether a fixed argument is passed as a floating-point value.
+fn is_float<T: 'static>() -> bool {
+  TypeId::of::<T>() == TypeId::of::<f32>() || TypeId::of::<T>() == TypeId::of::<f64>()
+}
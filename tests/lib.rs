@@ -47,6 +47,124 @@ mod raw {
     }
     Ok(())
   }
+
+  #[test]
+  #[cfg(all(unix, any(target_arch = "x86", target_arch = "x86_64")))]
+  fn unaligned_word() -> Result<()> {
+    type CRet = unsafe extern "C" fn() -> i32;
+
+    extern "C" fn ret10() -> i32 {
+      10
+    }
+
+    // mov eax, 7; ret (six bytes past an 8-byte boundary)
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let page = unsafe {
+      libc::mmap(
+        std::ptr::null_mut(),
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+        -1,
+        0,
+      )
+    };
+    assert_ne!(page, libc::MAP_FAILED);
+
+    let code = unsafe {
+      let code = (page as *mut u8).add(6);
+      let bytes = [0xB8, 0x07, 0x00, 0x00, 0x00, 0xC3];
+      std::ptr::copy_nonoverlapping(bytes.as_ptr(), code, bytes.len());
+      libc::mprotect(page, size, libc::PROT_READ | libc::PROT_EXEC);
+      code as *const ()
+    };
+
+    // The patch crosses an 8-byte boundary, so it cannot be written atomically
+    unsafe {
+      let target: CRet = mem::transmute(code);
+      let hook = RawDetour::new(code, ret10 as *const ())?;
+
+      hook.enable()?;
+      assert_eq!(target(), 10);
+      let original: CRet = mem::transmute(hook.trampoline());
+      assert_eq!(original(), 7);
+
+      hook.disable()?;
+      assert_eq!(target(), 7);
+      mem::drop(hook);
+      libc::munmap(page, size);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn info() -> Result<()> {
+    #[inline(never)]
+    extern "C" fn xor(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) ^ y }
+    }
+
+    unsafe {
+      let hook = RawDetour::new(xor as *const (), sub_detour as *const ())?;
+      let info = hook.info();
+
+      assert_eq!(info.target, xor as *const ());
+      assert_eq!(info.trampoline, hook.trampoline() as *const ());
+      assert!(!info.uses_hot_patch);
+      assert_eq!(info.patch_area.start, xor as *const () as usize);
+      assert_eq!(info.patched_bytes.len(), info.patch_area.len());
+      assert!(info.prolog_size >= info.patch_area.len());
+
+      let first = info.relocated_instructions[0];
+      assert_eq!(first.original, info.target);
+      assert_eq!(first.relocated, info.trampoline);
+
+      let prolog = std::slice::from_raw_parts(xor as *const u8, info.patch_area.len());
+      assert_eq!(prolog, info.original_bytes.as_slice());
+      hook.enable()?;
+      assert_eq!(prolog, info.patched_bytes.as_slice());
+    }
+    Ok(())
+  }
+}
+
+#[cfg(target_os = "linux")]
+mod symbol {
+  use super::*;
+  use detour::{GenericDetour, RawDetour};
+
+  #[inline(never)]
+  extern "C" fn mul(x: i32, y: i32) -> i32 {
+    unsafe { std::ptr::read_volatile(&x as *const i32) * y }
+  }
+
+  #[inline(never)]
+  #[no_mangle]
+  extern "C" fn detour_symbol_add(x: i32, y: i32) -> i32 {
+    unsafe { std::ptr::read_volatile(&x as *const i32) + y }
+  }
+
+  #[test]
+  fn demangled() -> Result<()> {
+    unsafe {
+      let hook = GenericDetour::<FnAdd>::from_symbol("", "lib::symbol::mul", sub_detour)?;
+      assert_eq!(hook.target(), mul as *const ());
+
+      hook.enable()?;
+      assert_eq!(mul(10, 5), 5);
+      assert_eq!(hook.call(10, 5), 50);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn unmangled() -> Result<()> {
+    unsafe {
+      let hook = RawDetour::from_symbol("", "detour_symbol_add", sub_detour as *const ())?;
+      assert_eq!(hook.target(), detour_symbol_add as *const ());
+    }
+    Ok(())
+  }
 }
 
 mod generic {
@@ -77,9 +195,328 @@ mod generic {
     }
     Ok(())
   }
+
+  #[test]
+  #[rustfmt::skip]
+  #[cfg(target_arch = "x86_64")]
+  fn many_arguments() -> Result<()> {
+    type FnWide = extern "win64" fn(
+      i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64,
+      i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64,
+    ) -> i64;
+
+    // Every argument is used, so none of them are optimized away
+    #[inline(never)]
+    extern "win64" fn sum(
+      a0: i64, a1: i64, a2: i64, a3: i64, a4: i64, a5: i64, a6: i64, a7: i64,
+      a8: i64, a9: i64, a10: i64, a11: i64, a12: i64, a13: i64, a14: i64, a15: i64,
+      a16: i64, a17: i64, a18: i64, a19: i64, a20: i64, a21: i64, a22: i64, a23: i64,
+    ) -> i64 {
+      let a0 = unsafe { std::ptr::read_volatile(&a0 as *const i64) };
+      a0 + a1 + a2 + a3 + a4 + a5 + a6 + a7 + a8 + a9 + a10 + a11 + a12 + a13 + a14 + a15
+        + a16 + a17 + a18 + a19 + a20 + a21 + a22 + a23
+    }
+
+    extern "win64" fn last(
+      a0: i64, a1: i64, a2: i64, a3: i64, a4: i64, a5: i64, a6: i64, a7: i64,
+      a8: i64, a9: i64, a10: i64, a11: i64, a12: i64, a13: i64, a14: i64, a15: i64,
+      a16: i64, a17: i64, a18: i64, a19: i64, a20: i64, a21: i64, a22: i64, a23: i64,
+    ) -> i64 {
+      a23 * 1000 + (a0 + a1 + a2 + a3 + a4 + a5 + a6 + a7 + a8 + a9 + a10 + a11 + a12 + a13
+        + a14 + a15 + a16 + a17 + a18 + a19 + a20 + a21 + a22) % 7
+    }
+
+    unsafe {
+      let hook = GenericDetour::<FnWide>::new(sum, last)?;
+      let call = || sum(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24);
+
+      assert_eq!(call(), 300);
+      hook.with_enabled(|| assert_eq!(call(), 24_003))?;
+      assert_eq!(hook.call(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24), 300);
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn toggled_under_load() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[inline(never)]
+    extern "C" fn add(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) + y }
+    }
+
+    extern "C" fn sub(x: i32, y: i32) -> i32 {
+      x - y
+    }
+
+    let hook = unsafe { GenericDetour::<FnAdd>::new(add, sub)? };
+    let done = Arc::new(AtomicBool::new(false));
+
+    let workers = (0..4)
+      .map(|_| {
+        let done = done.clone();
+        std::thread::spawn(move || {
+          while !done.load(Ordering::SeqCst) {
+            let result = add(5, 5);
+            assert!(result == 10 || result == 0);
+          }
+        })
+      })
+      .collect::<Vec<_>>();
+
+    for _ in 0..25 {
+      unsafe {
+        hook.enable()?;
+        hook.disable()?;
+      }
+    }
+
+    done.store(true, Ordering::SeqCst);
+    for worker in workers {
+      worker.join().unwrap();
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn with_closure() -> Result<()> {
+    #[inline(never)]
+    extern "C" fn add_thrice(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) + y * 3 }
+    }
+
+    #[inline(never)]
+    fn negate(x: f64) -> f64 {
+      unsafe { -std::ptr::read_volatile(&x as *const f64) }
+    }
+
+    unsafe {
+      // Each hook has its own captured state
+      let offset = 100;
+      let hook = GenericDetour::<FnAdd>::with_closure(add_thrice, move |x, y| x - y + offset)?;
+      let scale = 0.5;
+      let other = GenericDetour::<fn(f64) -> f64>::with_closure(negate, move |x| x * scale)?;
+
+      hook.enable()?;
+      other.enable()?;
+      assert_eq!(add_thrice(10, 5), 105);
+      assert_eq!(hook.call(10, 5), 25);
+      assert_eq!(negate(3.0), 1.5);
+      assert_eq!(other.call(3.0), -3.0);
+
+      hook.disable()?;
+      assert_eq!(add_thrice(10, 5), 25);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn reentrancy_guard() -> Result<()> {
+    #[inline(never)]
+    extern "C" fn shr(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) >> y }
+    }
+
+    unsafe {
+      let hook = GenericDetour::<FnAdd>::with_closure(shr, |x, y| shr(x, y) + 1)?;
+      hook.set_reentrancy_guard(true);
+      hook.enable()?;
+
+      // The nested call reaches the original function
+      assert_eq!(shr(12, 2), 4);
+      assert!(!hook.is_nested());
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn enable_scoped() -> Result<()> {
+    #[inline(never)]
+    extern "C" fn add_twice(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) + y * 2 }
+    }
+
+    unsafe {
+      let hook = GenericDetour::<FnAdd>::new(add_twice, sub_detour)?;
+
+      {
+        let _outer = hook.enable_scoped()?;
+        {
+          let _inner = hook.enable_scoped()?;
+          assert_eq!(add_twice(10, 5), 5);
+        }
+
+        // The detour remains enabled until the last scope ends
+        assert!(hook.is_enabled());
+        assert_eq!(add_twice(10, 5), 5);
+      }
+      assert!(!hook.is_enabled());
+      assert_eq!(add_twice(10, 5), 20);
+
+      // The detour is disabled whilst unwinding
+      let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        hook.with_enabled(|| {
+          assert_eq!(add_twice(10, 5), 5);
+          panic!("unwinding");
+        })
+      }));
+      assert!(result.is_err());
+      assert!(!hook.is_enabled());
+
+      // An explicitly enabled detour outlives any scope
+      hook.enable()?;
+      assert_eq!(hook.with_enabled(|| add_twice(10, 5))?, 5);
+      assert!(hook.is_enabled());
+      hook.disable()?;
+      assert_eq!(add_twice(10, 5), 20);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn enter() -> Result<()> {
+    use std::sync::OnceLock;
+
+    type FnIncrement = extern "C" fn(i32) -> i32;
+    static HOOK: OnceLock<GenericDetour<FnIncrement>> = OnceLock::new();
+
+    #[inline(never)]
+    extern "C" fn increment(x: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) + 1 }
+    }
+
+    extern "C" fn increment_detour(x: i32) -> i32 {
+      let hook = HOOK.get().unwrap();
+      match hook.enter() {
+        // The target is called within the detour
+        Some(_guard) => increment(x) * 2,
+        // The nested call is forwarded to the original
+        None => hook.call(x) + 100,
+      }
+    }
+
+    unsafe {
+      let hook = HOOK
+        .get_or_init(|| GenericDetour::<FnIncrement>::new(increment, increment_detour).unwrap());
+      hook.enable()?;
+
+      assert!(!hook.is_nested());
+      assert_eq!(increment(1), 204);
+      assert!(!hook.is_nested());
+
+      hook.disable()?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn per_thread() -> Result<()> {
+    use std::thread;
+
+    type FnSum = extern "C" fn(i32, i32, i32, i32, i32, i32, i32, i32, f64, f64) -> f64;
+
+    #[inline(never)]
+    extern "C" fn sum(
+      a: i32,
+      b: i32,
+      c: i32,
+      d: i32,
+      e: i32,
+      f: i32,
+      g: i32,
+      h: i32,
+      x: f64,
+      y: f64,
+    ) -> f64 {
+      let a = unsafe { std::ptr::read_volatile(&a as *const i32) };
+      f64::from(a + b + c + d + e + f + g + h) + x + y
+    }
+
+    extern "C" fn sum_detour(
+      a: i32,
+      b: i32,
+      c: i32,
+      d: i32,
+      e: i32,
+      f: i32,
+      g: i32,
+      h: i32,
+      x: f64,
+      y: f64,
+    ) -> f64 {
+      -(f64::from(a + b + c + d + e + f + g + h) + x + y)
+    }
+
+    let call = || sum(1, 2, 3, 4, 5, 6, 7, 8, 0.25, 0.5);
+
+    unsafe {
+      let hook = GenericDetour::<FnSum>::new(sum, sum_detour)?;
+      hook.set_per_thread(true)?;
+      hook.enable()?;
+
+      // Only threads which have opted in are detoured
+      assert_eq!(call(), 36.75);
+      hook.enable_for_current_thread()?;
+      assert!(hook.is_enabled_for_current_thread());
+      assert_eq!(call(), -36.75);
+      assert_eq!(thread::spawn(call).join().unwrap(), 36.75);
+
+      hook.disable_for_current_thread();
+      assert_eq!(call(), 36.75);
+
+      // The mode can be changed whilst enabled
+      hook.set_per_thread(false)?;
+      assert_eq!(thread::spawn(call).join().unwrap(), -36.75);
+      hook.disable()?;
+      assert_eq!(call(), 36.75);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn per_thread_key_reuse() -> Result<()> {
+    use std::sync::{Barrier, Mutex, OnceLock};
+    use std::thread;
+
+    #[inline(never)]
+    extern "C" fn mul(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) * y }
+    }
+
+    let first = Mutex::new(Some(unsafe {
+      GenericDetour::<FnAdd>::new(mul, sub_detour)?
+    }));
+    let second = OnceLock::new();
+    let barrier = Barrier::new(2);
+
+    thread::scope(|scope| {
+      let thread = scope.spawn(|| {
+        let first = first.lock().unwrap();
+        first.as_ref().unwrap().enable_for_current_thread().unwrap();
+        mem::drop(first);
+
+        barrier.wait();
+        barrier.wait();
+        second
+          .get()
+          .map(|hook: &GenericDetour<FnAdd>| hook.is_enabled_for_current_thread())
+      });
+
+      // The second detour may be allocated where the first one was
+      barrier.wait();
+      mem::drop(first.lock().unwrap().take());
+      let _ = second.set(unsafe { GenericDetour::<FnAdd>::new(mul, sub_detour).unwrap() });
+      barrier.wait();
+
+      assert_eq!(thread.join().unwrap(), Some(false));
+    });
+    Ok(())
+  }
 }
 
-#[cfg(feature = "nightly")]
 mod statik {
   use super::*;
   use detour::static_detour;
@@ -116,4 +553,913 @@ mod statik {
     }
     Ok(())
   }
+
+  #[test]
+  fn reset() -> Result<()> {
+    use detour::Error;
+    use matches::assert_matches;
+
+    #[inline(never)]
+    unsafe extern "C" fn div(x: i32, y: i32) -> i32 {
+      std::ptr::read_volatile(&x as *const i32) / y
+    }
+
+    #[inline(never)]
+    unsafe extern "C" fn rem(x: i32, y: i32) -> i32 {
+      std::ptr::read_volatile(&x as *const i32) % y
+    }
+
+    static_detour! {
+      static DetourReset: unsafe extern "C" fn(i32, i32) -> i32;
+    }
+
+    unsafe {
+      assert_matches!(DetourReset.reset(), Err(Error::NotInitialized));
+
+      DetourReset.initialize(div, |x, y| x - y)?.enable()?;
+      assert_eq!(div(10, 5), 5);
+
+      DetourReset.reset()?;
+      assert!(!DetourReset.is_enabled());
+      assert_eq!(div(10, 5), 2);
+
+      DetourReset
+        .initialize(rem, |x, y| DetourReset.call(x, y) + 1)?
+        .enable()?;
+      assert_eq!(rem(10, 4), 3);
+
+      let guard = DetourReset.enable_scoped()?;
+      assert_matches!(DetourReset.reset(), Err(Error::InUse));
+      assert_eq!(rem(10, 4), 3);
+      mem::drop(guard);
+
+      DetourReset.reset()?;
+      assert_eq!(rem(10, 4), 2);
+      assert_eq!(DetourReset.call(10, 4), 2);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn reset_concurrently() -> Result<()> {
+    use detour::Error;
+    use matches::assert_matches;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[inline(never)]
+    unsafe extern "C" fn xor(x: i32, y: i32) -> i32 {
+      std::ptr::read_volatile(&x as *const i32) ^ y
+    }
+
+    static_detour! {
+      static DetourXor: unsafe extern "C" fn(i32, i32) -> i32;
+    }
+
+    static RUNNING: AtomicBool = AtomicBool::new(true);
+
+    unsafe { DetourXor.initialize(xor, |x, y| x + y)? };
+
+    let scopes = (0..2)
+      .map(|_| {
+        thread::spawn(|| unsafe {
+          while RUNNING.load(Ordering::SeqCst) {
+            // The detour is either reset in its entirety, or not at all
+            if let Ok(guard) = DetourXor.enable_scoped() {
+              assert_eq!(xor(6, 3), 9);
+              mem::drop(guard);
+            }
+
+            // The original function remains callable whilst being reset, in
+            // which case the target is called, and may be detoured once again
+            assert_matches!(DetourXor.call(6, 3), 5 | 9);
+          }
+        })
+      })
+      .collect::<Vec<_>>();
+
+    for _ in 0..100 {
+      while let Err(error) = unsafe { DetourXor.reset() } {
+        assert_matches!(error, Error::InUse);
+        thread::yield_now();
+      }
+      unsafe { DetourXor.initialize(xor, |x, y| x + y)? };
+    }
+
+    RUNNING.store(false, Ordering::SeqCst);
+    for scope in scopes {
+      scope.join().unwrap();
+    }
+
+    unsafe { DetourXor.reset()? };
+    assert_eq!(unsafe { xor(6, 3) }, 5);
+    Ok(())
+  }
+
+  #[test]
+  fn reentrancy_guard() -> Result<()> {
+    #[inline(never)]
+    unsafe extern "C" fn shl(x: i32, y: i32) -> i32 {
+      std::ptr::read_volatile(&x as *const i32) << y
+    }
+
+    static_detour! {
+      static DetourNested: unsafe extern "C" fn(i32, i32) -> i32;
+    }
+
+    unsafe {
+      DetourNested.initialize(shl, |x, y| {
+        assert!(DetourNested.is_nested());
+        shl(x, y) + 1
+      })?;
+      DetourNested.set_reentrancy_guard(true);
+      DetourNested.enable()?;
+
+      assert_eq!(shl(3, 2), 13);
+      assert!(!DetourNested.is_nested());
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn panic_policy() -> Result<()> {
+    use detour::PanicPolicy;
+    use std::panic;
+
+    // The target must be able to unwind, for the panic to be propagated
+    #[inline(never)]
+    fn mul(x: i32, y: i32) -> i32 {
+      x.checked_mul(y).expect("overflow")
+    }
+
+    static_detour! {
+      static DetourPanic: fn(i32, i32) -> i32;
+    }
+
+    unsafe {
+      DetourPanic
+        .initialize(mul, |x, y| {
+          if y == 0 {
+            panic!("detour panic")
+          } else {
+            x + y
+          }
+        })?
+        .enable()?;
+      assert_eq!(mul(3, 4), 7);
+
+      DetourPanic.set_panic_policy(PanicPolicy::CallOriginal);
+      assert_eq!(mul(3, 0), 0);
+
+      DetourPanic.set_panic_policy(PanicPolicy::Fallback(Box::new(|| -1)));
+      assert_eq!(mul(3, 0), -1);
+
+      // Rust functions may unwind
+      DetourPanic.set_panic_policy(PanicPolicy::Propagate);
+      assert!(panic::catch_unwind(|| mul(3, 0)).is_err());
+      assert_eq!(mul(3, 4), 7);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn set_detour() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[inline(never)]
+    unsafe extern "C" fn neg(x: i32, _: i32) -> i32 {
+      -std::ptr::read_volatile(&x as *const i32)
+    }
+
+    static_detour! {
+      static DetourSwap: unsafe extern "C" fn(i32, i32) -> i32;
+    }
+
+    static RUNNING: AtomicBool = AtomicBool::new(true);
+
+    // Callers may leave the argument unused by `neg` unset
+    unsafe { DetourSwap.initialize(neg, |x, _| x + 5)?.enable()? };
+
+    let callers = (0..8)
+      .map(|_| {
+        thread::spawn(|| {
+          while RUNNING.load(Ordering::SeqCst) {
+            assert_eq!(unsafe { neg(10, 5) }, 15);
+          }
+        })
+      })
+      .collect::<Vec<_>>();
+
+    for _ in 0..10_000 {
+      // A heap allocation, to detect any closure used after being released
+      let offset = Box::new(5);
+      DetourSwap.set_detour(move |x, _| {
+        thread::yield_now();
+        x + *offset
+      });
+    }
+
+    RUNNING.store(false, Ordering::SeqCst);
+    for caller in callers {
+      caller.join().unwrap();
+    }
+
+    unsafe { DetourSwap.disable()? };
+    assert_eq!(unsafe { neg(10, 5) }, -10);
+    Ok(())
+  }
+}
+
+mod chain {
+  use super::*;
+  use detour::HookChain;
+
+  #[inline(never)]
+  extern "C" fn chain_add(x: i32, y: i32) -> i32 {
+    unsafe { std::ptr::read_volatile(&x as *const i32) + y }
+  }
+
+  #[test]
+  fn ordering() -> Result<()> {
+    let chain = unsafe { HookChain::<FnAdd>::new(chain_add)? };
+    let info = chain.info();
+
+    unsafe { chain.enable()? };
+    assert_eq!(chain_add(5, 5), 10);
+
+    let double = chain.insert(0, |next, x, y| next.call(x, y) * 2);
+    let triple = chain.insert(-10, |next, x, y| next.call(x * 3, y));
+    chain.insert(10, |next, x, y| next.call(x + 1, y));
+    assert_eq!(chain.len(), 3);
+
+    // ((5 + 1) * 3 + 5) * 2
+    assert_eq!(chain_add(5, 5), 46);
+
+    // Links can be removed at any position
+    assert!(chain.remove(double));
+    assert!(!chain.remove(double));
+    assert_eq!(chain_add(5, 5), 23);
+
+    // (5 * 3 + 1) + 5
+    assert!(chain.set_priority(triple, 20));
+    assert_eq!(chain_add(5, 5), 21);
+
+    // The prolog is never relocated again
+    assert_eq!(chain.info().trampoline, info.trampoline);
+
+    unsafe { chain.disable()? };
+    assert_eq!(chain_add(5, 5), 10);
+    Ok(())
+  }
+}
+
+mod hook {
+  use super::*;
+  use detour::hook;
+
+  #[inline(never)]
+  fn square(x: i32) -> i32 {
+    unsafe { std::ptr::read_volatile(&x as *const i32) * x }
+  }
+
+  #[inline(never)]
+  #[no_mangle]
+  extern "C" fn detour_hook_negate(x: i32) -> i32 {
+    unsafe { -std::ptr::read_volatile(&x as *const i32) }
+  }
+
+  #[inline(never)]
+  unsafe fn cube(x: i32) -> i32 {
+    std::ptr::read_volatile(&x as *const i32) * x * x
+  }
+
+  #[hook(target = square)]
+  fn square_hook(x: i32) -> i32 {
+    original(x) + 1
+  }
+
+  // A safe function may detour an `unsafe` target
+  #[hook(target = cube)]
+  fn cube_hook(x: i32) -> i32 {
+    original(x) - 1
+  }
+
+  #[cfg(target_os = "linux")]
+  #[hook(symbol = "detour_hook_negate")]
+  extern "C" fn negate_hook(x: i32) -> i32 {
+    original(x) * 2
+  }
+
+  #[test]
+  fn install_all() -> Result<()> {
+    assert_eq!(square(3), 9);
+    assert!(!square_hook.is_enabled());
+
+    unsafe { detour::install_all()? };
+    assert_eq!(square(3), 10);
+    assert_eq!(unsafe { cube(2) }, 7);
+
+    #[cfg(target_os = "linux")]
+    assert_eq!(detour_hook_negate(3), -6);
+
+    // Hooks which are already enabled are left as is
+    unsafe { detour::install_all()? };
+    assert_eq!(square(3), 10);
+
+    unsafe { square_hook.disable()? };
+    assert_eq!(square(3), 9);
+    Ok(())
+  }
+}
+
+mod vtable {
+  use super::*;
+  use detour::VTableDetour;
+  use std::ptr;
+
+  #[repr(C)]
+  struct Object {
+    vtable: *const FnMethod,
+    value: i32,
+  }
+
+  type FnMethod = extern "C" fn(*const Object, i32) -> i32;
+
+  #[inline(never)]
+  extern "C" fn scale(this: *const Object, x: i32) -> i32 {
+    unsafe { ptr::read_volatile(&(*this).value) * x }
+  }
+
+  #[inline(never)]
+  extern "C" fn offset(this: *const Object, x: i32) -> i32 {
+    unsafe { ptr::read_volatile(&(*this).value) + x }
+  }
+
+  extern "C" fn offset_detour(this: *const Object, x: i32) -> i32 {
+    unsafe { ptr::read_volatile(&(*this).value) - x }
+  }
+
+  /// Invokes a virtual method of an object.
+  fn invoke(object: &Object, index: usize, x: i32) -> i32 {
+    let method = unsafe { ptr::read_volatile(object.vtable.add(index)) };
+    method(object, x)
+  }
+
+  #[test]
+  fn in_place() -> Result<()> {
+    static VTABLE: [FnMethod; 2] = [scale, offset];
+
+    let first = Object {
+      vtable: VTABLE.as_ptr(),
+      value: 10,
+    };
+    let second = Object {
+      vtable: VTABLE.as_ptr(),
+      value: 20,
+    };
+
+    unsafe {
+      let hook = VTableDetour::<FnMethod>::new(VTABLE.as_ptr() as *const _, 1, offset_detour)?;
+      assert!(!hook.is_shadow());
+
+      hook.enable()?;
+      assert_eq!(invoke(&first, 1, 3), 7);
+      assert_eq!(invoke(&second, 1, 3), 17);
+      assert_eq!(invoke(&first, 0, 3), 30);
+      assert_eq!(hook.call(&first, 3), 13);
+
+      hook.disable()?;
+      assert_eq!(invoke(&first, 1, 3), 13);
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(unix, any(target_arch = "x86", target_arch = "x86_64")))]
+  fn in_place_beside_code() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    // A vtable sharing its page with code (mov eax, 7; ret)
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let page = unsafe {
+      libc::mmap(
+        ptr::null_mut(),
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+        -1,
+        0,
+      )
+    };
+    assert_ne!(page, libc::MAP_FAILED);
+
+    let vtable = unsafe {
+      let code = [0xB8, 0x07, 0x00, 0x00, 0x00, 0xC3];
+      ptr::copy_nonoverlapping(code.as_ptr(), page as *mut u8, code.len());
+
+      let vtable = (page as *mut u8).add(64) as *mut FnMethod;
+      vtable.write(scale);
+      vtable.add(1).write(offset);
+      libc::mprotect(page, size, libc::PROT_READ | libc::PROT_EXEC);
+      vtable
+    };
+
+    let code: extern "C" fn() -> i32 = unsafe { mem::transmute(page) };
+    let object = Object { vtable, value: 10 };
+    let running = AtomicBool::new(true);
+
+    // The code remains executable whilst the vtable is written
+    let hook = unsafe { VTableDetour::<FnMethod>::new(vtable as *const _, 1, offset_detour)? };
+    thread::scope(|scope| {
+      for _ in 0..2 {
+        scope.spawn(|| {
+          while running.load(Ordering::SeqCst) {
+            assert_eq!(code(), 7);
+          }
+        });
+      }
+
+      let toggled = (0..1000).try_for_each(|_| unsafe {
+        hook.enable()?;
+        assert_eq!(invoke(&object, 1, 3), 7);
+        hook.disable()
+      });
+
+      running.store(false, Ordering::SeqCst);
+      toggled
+    })?;
+
+    unsafe { libc::munmap(page, size) };
+    Ok(())
+  }
+
+  #[test]
+  fn shadow() -> Result<()> {
+    // The words preceding the methods (e.g RTTI) are copied as well
+    let vtable = [
+      0,
+      0,
+      scale as *const () as usize,
+      offset as *const () as usize,
+    ];
+    let methods = vtable[2..].as_ptr() as *const FnMethod;
+
+    let mut first = Object {
+      vtable: methods,
+      value: 10,
+    };
+    let second = Object {
+      vtable: methods,
+      value: 20,
+    };
+
+    unsafe {
+      let object = &mut first as *mut Object as *mut ();
+      let hook = VTableDetour::<FnMethod>::for_object(object, 1, 2, offset_detour)?;
+      assert!(hook.is_shadow());
+
+      hook.enable()?;
+      assert_ne!(first.vtable, methods);
+      assert_eq!(invoke(&first, 1, 3), 7);
+      assert_eq!(invoke(&first, 0, 3), 30);
+      assert_eq!(invoke(&second, 1, 3), 23);
+      assert_eq!(hook.call(&first, 3), 13);
+
+      mem::drop(hook);
+      assert_eq!(first.vtable, methods);
+      assert_eq!(invoke(&first, 1, 3), 13);
+    }
+    Ok(())
+  }
+}
+
+mod registry {
+  use super::*;
+  use detour::registry::{self, Event};
+  use detour::RawDetour;
+  use std::sync::{Arc, Mutex};
+
+  #[inline(never)]
+  extern "C" fn registry_add(x: i32, y: i32) -> i32 {
+    unsafe { std::ptr::read_volatile(&x as *const i32) + y + 1 }
+  }
+
+  #[test]
+  fn events() -> Result<()> {
+    let target = registry_add as *const ();
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    // Events of detours created by other tests are ignored
+    let (recorded, address) = (events.clone(), target as usize);
+    let subscription = registry::subscribe(move |event, entry| {
+      if entry.target as usize == address {
+        recorded.lock().unwrap().push(event);
+      }
+    });
+
+    unsafe {
+      let hook = RawDetour::new(target, sub_detour as *const ())?;
+      hook.set_label("registry_add");
+
+      let entries = registry::find(target);
+      assert_eq!(entries.len(), 1);
+      assert_eq!(entries[0].detour, sub_detour as *const ());
+      assert_eq!(entries[0].trampoline, hook.trampoline() as *const ());
+      assert_eq!(entries[0].label.as_deref(), Some("registry_add"));
+      assert!(!entries[0].enabled);
+
+      hook.enable()?;
+      assert!(registry::find(target)[0].enabled);
+      assert!(registry::detours()
+        .iter()
+        .any(|entry| entry.target == target));
+
+      // Enabling an enabled detour is not an event
+      hook.enable()?;
+      hook.disable()?;
+      hook.enable()?;
+    }
+
+    assert!(registry::find(target).is_empty());
+    mem::drop(subscription);
+
+    assert_eq!(
+      *events.lock().unwrap(),
+      [
+        Event::Created,
+        Event::Enabled,
+        Event::Disabled,
+        Event::Enabled,
+        Event::Disabled,
+        Event::Dropped,
+      ]
+    );
+    Ok(())
+  }
+}
+
+mod transaction {
+  use super::*;
+  use detour::{DetourTransaction, GenericDetour, RawDetour};
+
+  #[test]
+  fn test() -> Result<()> {
+    #[inline(never)]
+    extern "C" fn add(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) + y }
+    }
+
+    #[inline(never)]
+    extern "C" fn mul(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) * y }
+    }
+
+    unsafe {
+      let hook1 = GenericDetour::<FnAdd>::new(add, sub_detour)?;
+      let hook2 = RawDetour::new(mul as *const (), sub_detour as *const ())?;
+
+      let mut transaction = DetourTransaction::new();
+      transaction.enable(&hook1).enable(&hook2);
+      transaction.commit()?;
+
+      assert!(hook1.is_enabled() && hook2.is_enabled());
+      assert_eq!(add(10, 5), 5);
+      assert_eq!(mul(10, 5), 5);
+
+      let mut transaction = DetourTransaction::new();
+      transaction.disable(&hook1).disable(&hook2);
+      transaction.commit()?;
+
+      assert!(!hook1.is_enabled() && !hook2.is_enabled());
+      assert_eq!(add(10, 5), 15);
+      assert_eq!(mul(10, 5), 50);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn rollback() -> Result<()> {
+    use detour::{static_detour, Error};
+    use matches::assert_matches;
+
+    #[inline(never)]
+    extern "C" fn add(x: i32, y: i32) -> i32 {
+      unsafe { std::ptr::read_volatile(&x as *const i32) + y }
+    }
+
+    static_detour! {
+      static Uninitialized: extern "C" fn(i32, i32) -> i32;
+    }
+
+    unsafe {
+      let hook = GenericDetour::<FnAdd>::new(add, sub_detour)?;
+
+      let mut transaction = DetourTransaction::new();
+      transaction.enable(&hook).enable(&Uninitialized);
+      assert_matches!(transaction.commit(), Err(Error::NotInitialized));
+
+      assert!(!hook.is_enabled());
+      assert_eq!(add(10, 5), 15);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(target_os = "linux")]
+mod import {
+  use super::*;
+  use detour::{Error, ImportDetour};
+  use matches::assert_matches;
+
+  type FnGetpgrp = unsafe extern "C" fn() -> libc::pid_t;
+
+  #[test]
+  fn test() -> Result<()> {
+    unsafe extern "C" fn getpgrp_detour() -> libc::pid_t {
+      -1
+    }
+
+    unsafe {
+      let group = libc::getpgrp();
+      let hook = ImportDetour::<FnGetpgrp>::new("", "getpgrp", getpgrp_detour as FnGetpgrp)?;
+
+      hook.enable()?;
+      assert!(hook.is_enabled());
+      assert_eq!(libc::getpgrp(), -1);
+      assert_eq!(hook.call(), group);
+
+      hook.disable()?;
+      assert_eq!(libc::getpgrp(), group);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn lazy_binding() -> Result<()> {
+    use std::ffi::CString;
+    use std::process::Command;
+
+    unsafe extern "C" fn getpgrp_detour() -> libc::pid_t {
+      -2
+    }
+
+    // A library whose imports are bound once they're first called
+    let directory = std::env::temp_dir().join(format!("detour-lazy-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let source = directory.join("lazy.c");
+    let library = directory.join("libdetourlazy.so");
+    std::fs::write(
+      &source,
+      "#include <unistd.h>\nint lazy_getpgrp(void) { return getpgrp(); }\n",
+    )
+    .unwrap();
+
+    let status = Command::new("cc")
+      .args(&["-shared", "-fPIC", "-Wl,-z,lazy", "-o"])
+      .arg(&library)
+      .arg(&source)
+      .status()
+      .unwrap();
+    assert!(status.success());
+
+    unsafe {
+      let path = CString::new(library.to_str().unwrap()).unwrap();
+      let handle = libc::dlopen(path.as_ptr(), libc::RTLD_LAZY);
+      assert!(!handle.is_null());
+
+      let symbol = libc::dlsym(handle, b"lazy_getpgrp\0".as_ptr() as *const _);
+      let lazy_getpgrp: FnGetpgrp = std::mem::transmute(symbol);
+
+      let group = libc::getpgrp();
+      let hook = ImportDetour::<FnGetpgrp>::new(
+        library.to_str().unwrap(),
+        "getpgrp",
+        getpgrp_detour as FnGetpgrp,
+      )?;
+
+      // Calling the original function must not bind the entry
+      hook.enable()?;
+      assert_eq!(hook.call(), group);
+      assert_eq!(lazy_getpgrp(), -2);
+
+      hook.disable()?;
+      assert_eq!(lazy_getpgrp(), group);
+    }
+
+    std::fs::remove_dir_all(&directory).unwrap();
+    Ok(())
+  }
+
+  #[test]
+  fn not_found() {
+    unsafe extern "C" fn detour() -> libc::pid_t {
+      0
+    }
+
+    unsafe {
+      let module = ImportDetour::<FnGetpgrp>::new("libmissing.so", "getpgrp", detour as FnGetpgrp);
+      assert_matches!(module, Err(Error::ModuleNotFound));
+
+      let symbol = ImportDetour::<FnGetpgrp>::new("", "missing_import", detour as FnGetpgrp);
+      assert_matches!(symbol, Err(Error::SymbolNotFound));
+    }
+  }
+}
+
+mod signature {
+  use super::*;
+  use detour::signature::Signature;
+  use detour::Error;
+  use matches::assert_matches;
+
+  #[test]
+  fn scan() -> Result<()> {
+    // call +0x10; call +0x10
+    let code = [0xE8, 0x10, 0x00, 0x00, 0x00, 0xE8, 0x10, 0x00, 0x00, 0x00];
+
+    unsafe {
+      let second = code[5..].as_ptr() as usize;
+      let address = Signature::new("E8 ?? ?? ?? ?? E8")?.scan(&code)?;
+      assert_eq!(address, code.as_ptr() as *const ());
+
+      let destination = Signature::new("00 E8 ? ? ? ?")?.follow(1).scan(&code)?;
+      assert_eq!(destination, (second + 5 + 0x10) as *const ());
+
+      assert_matches!(
+        Signature::new("E8 10")?.scan(&code),
+        Err(Error::AmbiguousPattern)
+      );
+      assert_matches!(
+        Signature::new("E9")?.scan(&code),
+        Err(Error::PatternNotFound)
+      );
+    }
+
+    assert_matches!(Signature::new("E8 1"), Err(Error::InvalidPattern));
+    assert_matches!(Signature::new("?? ??"), Err(Error::InvalidPattern));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn scan_module() -> Result<()> {
+    let function = sub_detour as *const u8;
+    let pattern = (0..32)
+      .map(|index| format!("{:02X}", unsafe { *function.add(index) }))
+      .collect::<Vec<_>>()
+      .join(" ");
+
+    let address = unsafe { Signature::new(&pattern)?.scan_module("")? };
+    assert_eq!(address, sub_detour as *const ());
+    Ok(())
+  }
+}
+
+#[cfg(target_os = "linux")]
+mod variadic {
+  use super::*;
+  use detour::GenericDetour;
+  use std::ffi::{CStr, CString};
+  use std::os::raw::c_char;
+  use std::process::Command;
+  use std::sync::OnceLock;
+
+  type FnSum = unsafe extern "C" fn(usize, ...) -> f64;
+  type FnFormat = unsafe extern "C" fn(*mut c_char, usize, *const c_char, ...) -> i32;
+
+  const SOURCE: &str = r#"
+    #include <stdarg.h>
+    #include <stddef.h>
+    #include <stdio.h>
+
+    double variadic_sum(size_t count, ...) {
+      va_list list;
+      double total = 0.0;
+      va_start(list, count);
+      for (size_t i = 0; i < count; i++) {
+        int factor = va_arg(list, int);
+        total += factor * va_arg(list, double);
+      }
+      va_end(list);
+      return total;
+    }
+
+    int variadic_format(char *buffer, size_t size, const char *format, ...) {
+      va_list list;
+      va_start(list, format);
+      int length = vsnprintf(buffer, size, format, list);
+      va_end(list);
+      return length;
+    }
+  "#;
+
+  /// Returns a function of a C library, compiled once per process.
+  unsafe fn function<T: Copy>(name: &[u8]) -> T {
+    static LIBRARY: OnceLock<usize> = OnceLock::new();
+
+    let handle = *LIBRARY.get_or_init(|| {
+      let directory = std::env::temp_dir().join(format!("detour-variadic-{}", std::process::id()));
+      std::fs::create_dir_all(&directory).unwrap();
+      let source = directory.join("variadic.c");
+      let library = directory.join("libdetourvariadic.so");
+      std::fs::write(&source, SOURCE).unwrap();
+
+      let status = Command::new("cc")
+        .args(["-shared", "-fPIC", "-o"])
+        .arg(&library)
+        .arg(&source)
+        .status()
+        .unwrap();
+      assert!(status.success());
+
+      let path = CString::new(library.to_str().unwrap()).unwrap();
+      let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW);
+      assert!(!handle.is_null());
+      std::fs::remove_dir_all(&directory).unwrap();
+      handle as usize
+    });
+
+    let symbol = libc::dlsym(handle as *mut _, name.as_ptr() as *const _);
+    assert!(!symbol.is_null());
+    mem::transmute_copy(&symbol)
+  }
+
+  #[test]
+  fn sum() -> Result<()> {
+    static HOOK: OnceLock<GenericDetour<FnSum>> = OnceLock::new();
+    let sum: FnSum = unsafe { function(b"variadic_sum\0") };
+
+    let hook = HOOK.get_or_init(|| unsafe {
+      GenericDetour::<FnSum>::with_closure(sum, |count, arguments| {
+        let mut list = arguments.list();
+        let total = (0..count)
+          .map(|_| list.arg::<i32>() as f64 * list.arg::<f64>())
+          .sum::<f64>();
+
+        // The same arguments are forwarded, except for the count
+        total * 2.0 + HOOK.get().unwrap().call(count - 1, arguments)
+      })
+      .unwrap()
+    });
+
+    // Both the registers and the stack are used for the arguments
+    let call = || unsafe {
+      sum(
+        10, 1, 0.5, 2, 1.0, 3, 1.5, 4, 2.0, 5, 2.5, 6, 3.0, 7, 3.5, 8, 4.0, 9, 4.5, 10, 5.0,
+      )
+    };
+
+    assert_eq!(call(), 192.5);
+    unsafe { hook.enable()? };
+    assert_eq!(call(), 385.0 + 142.5);
+    unsafe { hook.disable()? };
+    assert_eq!(call(), 192.5);
+    Ok(())
+  }
+
+  #[test]
+  fn format() -> Result<()> {
+    static HOOK: OnceLock<GenericDetour<FnFormat>> = OnceLock::new();
+    let format: FnFormat = unsafe { function(b"variadic_format\0") };
+
+    let hook = HOOK.get_or_init(|| unsafe {
+      GenericDetour::<FnFormat>::with_closure(format, |buffer, size, _, arguments| {
+        let mut list = arguments.list();
+        assert_eq!(
+          CStr::from_ptr(list.arg::<*const c_char>()).to_str(),
+          Ok("one")
+        );
+        assert_eq!(list.arg::<i32>(), 3);
+        assert_eq!(list.arg::<f64>(), 1.5);
+
+        // The variadic arguments are forwarded with another format
+        let format = b"[%s|%d|%.1f]\0".as_ptr() as *const c_char;
+        HOOK.get().unwrap().call(buffer, size, format, arguments)
+      })
+      .unwrap()
+    });
+
+    let mut buffer = [0 as c_char; 32];
+    let mut call = || unsafe {
+      let length = format(
+        buffer.as_mut_ptr(),
+        buffer.len(),
+        b"%s %d %.2f\0".as_ptr() as *const c_char,
+        b"one\0".as_ptr() as *const c_char,
+        3,
+        1.5,
+      );
+      let text = CStr::from_ptr(buffer.as_ptr()).to_str().unwrap().to_owned();
+      (length, text)
+    };
+
+    assert_eq!(call(), (10, "one 3 1.50".to_owned()));
+    unsafe { hook.enable()? };
+    assert_eq!(call(), (11, "[one|3|1.5]".to_owned()));
+    unsafe { hook.disable()? };
+    assert_eq!(call(), (10, "one 3 1.50".to_owned()));
+    Ok(())
+  }
 }